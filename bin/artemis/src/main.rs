@@ -1,20 +1,41 @@
 use anyhow::Result;
 use artemis_core::{
     collectors::{
+        account_collector::AccountCollector,
         block_collector::BlockCollector,
-        log_collector::{EventFilter, LogCollector},
+        execution_result_collector::ExecutionResultCollector,
+        ledger_entry_collector::LedgerEntryCollector,
+        log_collector::{EventFilter, LogCollector, LogEvent},
+        replay_collector::ReplayCollector,
     },
-    engine::Engine,
-    executors::soroban_executor::SorobanExecutor,
-    types::{CollectorMap, ExecutorMap},
+    delivery::Delivery,
+    engine::{Engine, ExecutorConfig, StrategyConfig, SyncPolicy},
+    executors::{
+        layers::{
+            deadline::DeadlineLayer, dedup::DedupLayer, rate_limit::RateLimitLayer, ExecutorStack,
+        },
+        simulating_executor::SimulatingExecutor,
+        soroban_executor::{SorobanExecutor, SubmitStellarTx},
+    },
+    recorder::FileRecorder,
+    types::{Collector, CollectorMap, Executor, ExecutorMap},
+    utilities::{backoff::Backoff, fee_policy::FeePolicy, notifier::SlackNotifier},
 };
 use blend_strategies::{
     auctioneer_strategy::BlendAuctioneer,
+    backfill::UserBackfill,
+    db_manager::DbManager,
     liquidation_strategy::BlendLiquidator,
+    pool_collector::PoolCollector,
+    price_collector::PriceCollector,
+    tx_store::TxStore,
     types::{Action, Config, Event},
+    wallet_collector::WalletCollector,
 };
 use clap::Parser;
 use ed25519_dalek::SigningKey;
+use futures::StreamExt;
+use replay::{ActionLog, RpcStub};
 use stellar_rpc_client::EventType;
 use stellar_strkey::ed25519::PrivateKey;
 
@@ -22,11 +43,17 @@ use core::panic;
 use serde_json;
 use std::{
     fs::{self, OpenOptions},
+    net::SocketAddr,
     path::Path,
     sync::Arc,
+    time::Duration,
 };
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 use tracing_subscriber::{filter, prelude::*};
+
+mod replay;
+mod status;
+
 /// CLI Options.
 #[derive(Parser, Debug)]
 pub struct Args {
@@ -35,13 +62,102 @@ pub struct Args {
     /// Private key for sending txs.
     #[arg(long)]
     pub private_key: String,
+    /// Private key of a separate account to pay every transaction fee from,
+    /// through fee bumps.
+    #[arg(long)]
+    pub fee_private_key: Option<String>,
+    /// Alert once the account paying fees has fewer than this many stroops to
+    /// spend. Fails at startup without `horizon_url` to follow the account.
+    #[arg(long)]
+    pub fee_alert_balance: Option<i64>,
+    /// Seconds to keep submitting queued transactions after a shutdown signal.
+    #[arg(long, default_value_t = 30)]
+    pub shutdown_timeout: u64,
+    /// Most transactions submitted at once. Transactions from the same signer
+    /// are sent in sequence order, and wait for inclusion side by side.
+    #[arg(long, default_value_t = 4)]
+    pub executor_concurrency: usize,
+    /// Seconds between polls of the oracle for price changes.
+    #[arg(long, default_value_t = 10)]
+    pub price_poll_interval: u64,
+    /// Seconds between polls of the watched ledger entries.
+    #[arg(long, default_value_t = 5)]
+    pub ledger_entry_poll_interval: u64,
+    /// Seconds between polls of our token balances.
+    #[arg(long, default_value_t = 5)]
+    pub wallet_poll_interval: u64,
+    /// Seconds between polls of our classic account, if `horizon_url` is set.
+    #[arg(long, default_value_t = 5)]
+    pub account_poll_interval: u64,
+    /// Milliseconds between polls for new ledgers.
+    #[arg(long, default_value_t = 1000)]
+    pub block_poll_interval: u64,
+    /// Percentile of recent network inclusion fees to bid.
+    #[arg(long, default_value_t = 90)]
+    pub fee_percentile: u32,
+    /// Percent the inclusion fee of a fill with a target ledger rises by on each
+    /// retry. The total fee stays capped at `bid_percentage` of the profit.
+    #[arg(long, default_value_t = 50)]
+    pub fee_escalation_pct: u32,
+    /// Drop a contract call repeated within this many ledgers. 0 disables it.
+    #[arg(long, default_value_t = 5)]
+    pub dedup_ledgers: u32,
+    /// Most transactions submitted to a single pool per `pool_rate_window`. 0
+    /// disables the limit.
+    #[arg(long, default_value_t = 0)]
+    pub pool_rate_limit: usize,
+    /// Seconds the pool rate limit is counted over.
+    #[arg(long, default_value_t = 60)]
+    pub pool_rate_window: u64,
+    /// Drop transactions whose target ledger has passed.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub deadline_filter: bool,
+    /// Append every event the engine sees to this file.
+    #[arg(long)]
+    pub record: Option<String>,
+    /// Replay the events recorded in this file instead of following the chain.
+    /// Actions are written to `replay_actions.txt` in the db directory instead of
+    /// being submitted, so point `db_path` at a copy of the production databases.
+    #[arg(long)]
+    pub replay: Option<String>,
+    /// JSON file of RPC responses to answer the strategies with during a replay.
+    #[arg(long, requires = "replay")]
+    pub rpc_stub: Option<String>,
+    /// Simulate transactions and append them to `paper_trades.jsonl` in the db
+    /// directory instead of sending them.
+    #[arg(long, conflicts_with = "replay")]
+    pub dry_run: bool,
+    /// Print every recorded attempt at submitting the transaction with this hash,
+    /// or the action with this id, as JSON and exit.
+    #[arg(long)]
+    pub lookup_tx: Option<String>,
+    /// Serve the status of every engine component as JSON on
+    /// `http://<status_addr>/status`, e.g. `127.0.0.1:9100`.
+    #[arg(long)]
+    pub status_addr: Option<SocketAddr>,
+    /// Before starting, add every user with a position event in the configured
+    /// pools since this ledger to the users db. An interrupted backfill resumes
+    /// from where it stopped instead.
+    #[arg(long, conflicts_with = "replay")]
+    pub backfill_from: Option<u32>,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
     let config_data = fs::read_to_string(args.config_path).expect("Unable to read config file");
-    let config: Config = serde_json::from_str(&config_data).expect("Unable to parse json");
+    let mut config: Config = serde_json::from_str(&config_data).expect("Unable to parse json");
+
+    // Answer a lookup from the transaction store without starting the bot
+    if let Some(lookup) = &args.lookup_tx {
+        let tx_store = TxStore::new(&config.db_path)?;
+        let attempts = match tx_store.attempt_by_hash(lookup)? {
+            Some(attempt) => vec![attempt],
+            None => tx_store.attempts(lookup)?,
+        };
+        println!("{}", serde_json::to_string_pretty(&attempts)?);
+        return Ok(());
+    }
 
     // Set up tracing and parse args.
     let filter = filter::Targets::new()
@@ -50,6 +166,7 @@ async fn main() -> Result<()> {
         .with_target("blend_strategies::liquidation_strategy", Level::INFO)
         .with_target("blend_strategies::auction_manager", Level::INFO)
         .with_target("blend_strategies::db_manager", Level::INFO)
+        .with_target("blend_strategies::backfill", Level::INFO)
         .with_target("blend_strategies::helper", Level::INFO);
 
     let log_file = OpenOptions::new()
@@ -68,54 +185,355 @@ async fn main() -> Result<()> {
 
     let signing_key =
         SigningKey::from_bytes(&PrivateKey::from_string(&args.private_key).unwrap().0);
+    let fee_key = args
+        .fee_private_key
+        .as_ref()
+        .map(|key| SigningKey::from_bytes(&PrivateKey::from_string(key).unwrap().0));
+    if args.fee_alert_balance.is_some() && config.horizon_url.is_none() {
+        return Err(anyhow::anyhow!(
+            "--fee-alert-balance requires horizon_url to follow the fee account"
+        ));
+    }
+
+    if args.replay.is_some() {
+        // a replay must not alert anyone or reach the network
+        config.slack_api_url_key = String::new();
+        if let Some(rpc_stub) = &args.rpc_stub {
+            config.rpc_url = RpcStub::load(rpc_stub)?.serve()?;
+            config.rpc_fallback_urls.clear();
+            info!("replaying against rpc stub at {}", config.rpc_url);
+        }
+    }
 
     // Set up engine.
-    let mut engine: Engine<Event, Action> = Engine::default();
+    let mut engine: Engine<Event, Action> = Engine::default()
+        .with_shutdown_timeout(Duration::from_secs(args.shutdown_timeout))
+        .with_notifier(Arc::new(SlackNotifier::new(&config.slack_api_url_key)));
+    if let Some(record) = &args.record {
+        engine = engine.with_recorder(Box::new(FileRecorder::new(record)?));
+    }
 
-    // Set up log collector
+    // Every component shares one client, so they agree on which endpoint is healthy
+    // and only one task checks the endpoints' health
+    let rpc = config.rpc_client()?;
 
-    let log_collector = Box::new(LogCollector::new(
-        config.rpc_url.clone(),
-        EventFilter {
-            event_type: EventType::Contract,
-            contract_ids: config.pools.clone(),
-            topics: vec![],
-        },
-    ));
-    let log_collector = CollectorMap::new(log_collector, |e| Event::SorobanEvents(Box::new(e)));
-    engine.add_collector(Box::new(log_collector));
-
-    // Set up block collector.
-    let block_collector = Box::new(BlockCollector::new(config.rpc_url.clone()));
-    let block_collector = CollectorMap::new(block_collector, |e| Event::NewBlock(Box::new(e)));
-    engine.add_collector(Box::new(block_collector));
-
-    // Set up strategies.
-    let strategy = BlendAuctioneer::new(&config, &signing_key).await?;
-    engine.add_strategy(Box::new(strategy));
-    let strategy = BlendLiquidator::new(&config, &signing_key).await?;
-    engine.add_strategy(Box::new(strategy));
-
-    // Set up soroban executor.
-    let executor = Box::new(
-        SorobanExecutor::new(
-            &config.rpc_url,
-            &config.network_passphrase.clone(),
-            &config.db_path,
-            &config.slack_api_url_key,
+    let mut fee_account_collector = None;
+    if let Some(replay) = &args.replay {
+        let replay_collector = ReplayCollector::<Event>::new(replay.clone());
+        engine.add_collector(Box::new(replay_collector));
+
+        // Replayed events arrive as fast as the strategies take them, so nothing may be dropped
+        let replay_config =
+            StrategyConfig::default().with_delivery(Delivery::Lossless { capacity: 512 });
+        let strategy = BlendAuctioneer::new(rpc.clone(), &config, &signing_key).await?;
+        engine.add_strategy_with_config(Box::new(strategy), replay_config.clone());
+        let strategy = BlendLiquidator::new(rpc.clone(), &config, &signing_key).await?;
+        engine.add_strategy_with_config(Box::new(strategy), replay_config);
+
+        let action_log = ActionLog::new(
+            &Path::new(&config.db_path)
+                .join("replay_actions.txt")
+                .to_string_lossy(),
+        );
+        engine.add_executor_with_config(
+            Box::new(action_log),
+            ExecutorConfig::default().with_delivery(Delivery::Lossless { capacity: 512 }),
+        );
+    } else {
+        // Set up pool discovery, adopting the pools discovered in earlier runs first
+        let mut discovered_pools = None;
+        if let Some(pool_factory) = config.pool_factory.clone() {
+            let db_manager = DbManager::new(config.db_path.clone());
+            db_manager.initialize(&vec![])?;
+            for pool in db_manager.get_discovered_pools()? {
+                if config.pool_filter.adopts(&pool) && !config.pools.contains(&pool) {
+                    config.pools.push(pool);
+                }
+            }
+            let pool_collector = PoolCollector::new(
+                rpc.clone(),
+                pool_factory,
+                config.backstop.clone(),
+                config.pools.clone(),
+            )
+            .with_filter(config.pool_filter.clone())
+            .with_db_path(&config.db_path);
+            discovered_pools = Some(pool_collector.pools());
+            let pool_collector = CollectorMap::new(Box::new(pool_collector), |discovered| {
+                Event::PoolDiscovered(Box::new(discovered))
+            });
+            engine.add_collector(Box::new(pool_collector));
+        }
+
+        // Backfill users before the strategies load them
+        if let Some(start_ledger) = args.backfill_from {
+            let users = UserBackfill::new(
+                rpc.clone(),
+                config.pools.clone(),
+                DbManager::new(config.db_path.clone()),
+            )
+            .run(start_ledger)
+            .await?;
+            info!("user backfill finished, {} users found", users);
+        }
+
+        // Set up log collector, following discovered pools if any
+        let mut log_collector = LogCollector::new(
+            rpc.clone(),
+            EventFilter {
+                event_type: EventType::Contract,
+                contract_ids: config.pools.clone(),
+                topics: vec![],
+            },
         )
-        .await,
-    );
-    let executor = ExecutorMap::new(executor, |action| match action {
-        Action::SubmitTx(tx) => Some(tx),
-    });
-    engine.add_executor(Box::new(executor));
+        .with_cursor_path(Path::new(&config.db_path).join("log_cursor.json"));
+        if let Some(discovered_pools) = discovered_pools {
+            log_collector = log_collector.with_contract_ids(discovered_pools);
+        }
+        let log_collector = Box::new(log_collector);
+        let log_collector = CollectorMap::new(log_collector, |e| match e {
+            LogEvent::Event(e) => Event::SorobanEvents(Box::new(e)),
+            LogEvent::Gap(gap) => Event::EventGap(Box::new(gap)),
+        });
+        engine.add_collector(Box::new(log_collector));
 
-    // Start engine.
-    if let Ok(mut set) = engine.run().await {
-        while let Some(res) = set.join_next().await {
-            info!("res: {:?}", res);
+        // Set up block collector.
+        let block_collector = Box::new(
+            BlockCollector::new(rpc.clone())
+                .with_poll_interval(Duration::from_millis(args.block_poll_interval)),
+        );
+        let latest_ledger = block_collector.latest_ledger();
+        let block_collector = CollectorMap::new(block_collector, |e| Event::NewBlock(Box::new(e)));
+        engine.add_collector(Box::new(block_collector));
+
+        // Set up price collector for every asset we hold or receive.
+        let mut assets = config.supported_collateral.clone();
+        assets.extend(config.supported_liabilities.clone());
+        assets.push(config.xlm_address.clone());
+        assets.sort();
+        assets.dedup();
+        let price_collector = PriceCollector::new(
+            rpc.clone(),
+            config.oracle_id.clone(),
+            config.oracle_decimals,
+            assets,
+        )
+        .with_poll_interval(Duration::from_secs(args.price_poll_interval));
+        let price_collector = CollectorMap::new(Box::new(price_collector), |update| {
+            Event::PriceUpdate(Box::new(update))
+        });
+        engine.add_collector(Box::new(price_collector));
+
+        // Set up ledger entry collector, watching the keys strategies ask for.
+        let ledger_entry_collector = LedgerEntryCollector::new(rpc.clone())
+            .with_poll_interval(Duration::from_secs(args.ledger_entry_poll_interval));
+        let watched_keys = ledger_entry_collector.watched_keys();
+        let ledger_entry_collector =
+            CollectorMap::new(Box::new(ledger_entry_collector), |change| {
+                Event::LedgerEntryChange(Box::new(change))
+            });
+        engine.add_collector(Box::new(ledger_entry_collector));
+
+        // Set up wallet collector for every token the liquidator bids with.
+        let mut tokens = config.supported_liabilities.clone();
+        tokens.push(config.backstop_token_address.clone());
+        tokens.sort();
+        tokens.dedup();
+        let wallet_collector = WalletCollector::new(
+            rpc.clone(),
+            stellar_strkey::ed25519::PublicKey(signing_key.verifying_key().to_bytes()).to_string(),
+            tokens,
+        )
+        .with_poll_interval(Duration::from_secs(args.wallet_poll_interval));
+        let wallet_collector = CollectorMap::new(Box::new(wallet_collector), |update| {
+            Event::BalanceUpdate(Box::new(update))
+        });
+        engine.add_collector(Box::new(wallet_collector));
+
+        // Set up account collector, if there is a Horizon server to follow our account through.
+        let mut account = None;
+        if let Some(horizon_url) = &config.horizon_url {
+            let account_collector = AccountCollector::new(
+                horizon_url,
+                &stellar_strkey::ed25519::PublicKey(signing_key.verifying_key().to_bytes())
+                    .to_string(),
+            )
+            .with_poll_interval(Duration::from_secs(args.account_poll_interval));
+            account = Some(account_collector.account());
+            let account_collector = CollectorMap::new(Box::new(account_collector), |event| {
+                Event::AccountEvent(Box::new(event))
+            });
+            engine.add_collector(Box::new(account_collector));
+        }
+        // The fee account is followed outside the engine, since only the executor needs it
+        let mut fee_account = account.clone();
+        if let (Some(horizon_url), Some(fee_key)) = (&config.horizon_url, &fee_key) {
+            let collector = AccountCollector::new(
+                horizon_url,
+                &stellar_strkey::ed25519::PublicKey(fee_key.verifying_key().to_bytes()).to_string(),
+            )
+            .with_poll_interval(Duration::from_secs(args.account_poll_interval));
+            fee_account = Some(collector.account());
+            fee_account_collector = Some(collector);
+        }
+
+        // Set up execution result collector, fed by the executor.
+        let result_collector = ExecutionResultCollector::new();
+        let result_sender = result_collector.sender();
+        let result_collector = CollectorMap::new(Box::new(result_collector), |r| {
+            Event::ExecutionResult(Box::new(r))
+        });
+        engine.add_collector(Box::new(result_collector));
+
+        // Set up strategies.
+        // The auctioneer can run on partial state and catch up on a later resync
+        let strategy = BlendAuctioneer::new(rpc.clone(), &config, &signing_key).await?;
+        engine.add_strategy(Box::new(strategy));
+        // The liquidator can't miss fill events, so it gets every event even if that slows collectors,
+        // and it won't start without knowing the ongoing auctions
+        let mut strategy = BlendLiquidator::new(rpc.clone(), &config, &signing_key)
+            .await?
+            .with_watched_keys(watched_keys)
+            .with_wallet_updates();
+        if account.is_some() {
+            strategy = strategy.with_account_events();
+        }
+        if fee_key.is_some() {
+            strategy = strategy.with_fee_account();
+        }
+        engine.add_strategy_with_config(
+            Box::new(strategy),
+            StrategyConfig::default()
+                .with_delivery(Delivery::Lossless { capacity: 512 })
+                .with_sync_policy(SyncPolicy::Retry {
+                    max_attempts: 5,
+                    backoff: Backoff::default(),
+                }),
+        );
+
+        // Set up soroban executor, wrapped in the configured layers.
+        let mut layers = ExecutorStack::new();
+        if args.dedup_ledgers > 0 {
+            layers = layers.layer(
+                DedupLayer::new(args.dedup_ledgers, latest_ledger.clone())
+                    .with_result_sender(result_sender.clone()),
+            );
         }
+        if args.pool_rate_limit > 0 {
+            layers = layers.layer(RateLimitLayer::new(
+                args.pool_rate_limit,
+                Duration::from_secs(args.pool_rate_window),
+            ));
+        }
+        if args.deadline_filter {
+            layers = layers
+                .layer(DeadlineLayer::new(latest_ledger).with_result_sender(result_sender.clone()));
+        }
+        let fee_policy = FeePolicy::new()
+            .with_percentile(args.fee_percentile)
+            .with_escalation_pct(args.fee_escalation_pct);
+        let executor: Box<dyn Executor<SubmitStellarTx>> = if args.dry_run {
+            info!("dry run, transactions are simulated and recorded instead of sent");
+            Box::new(
+                SimulatingExecutor::new(rpc, Path::new(&config.db_path).join("paper_trades.jsonl"))
+                    .with_fee_policy(fee_policy),
+            )
+        } else {
+            let mut soroban_executor = SorobanExecutor::new(
+                rpc,
+                &config.network_passphrase.clone(),
+                &config.db_path,
+                &config.slack_api_url_key,
+            )
+            .await
+            .with_result_sender(result_sender)
+            .with_fee_policy(fee_policy)
+            .with_attempt_store(Arc::new(TxStore::new(&config.db_path)?));
+            if let Some(fee_account) = fee_account {
+                soroban_executor = soroban_executor.with_fee_reserve(fee_account);
+            }
+            if let Some(fee_key) = fee_key {
+                soroban_executor = soroban_executor.with_fee_account(fee_key);
+            }
+            if let Some(fee_alert_balance) = args.fee_alert_balance {
+                soroban_executor =
+                    soroban_executor.with_low_balance_alert(fee_alert_balance as i128);
+            }
+            Box::new(soroban_executor)
+        };
+        let executor = layers.build(executor);
+        let executor = ExecutorMap::new(executor, |action| match action {
+            Action::SubmitTx(tx) => Some(tx),
+        });
+        engine.add_executor_with_config(
+            Box::new(executor),
+            ExecutorConfig::default().with_concurrency(args.executor_concurrency),
+        );
     }
+
+    // Start engine.
+    let handle = engine
+        .run()
+        .await
+        .map_err(|e| anyhow::anyhow!("Engine failed to start: {}", e))?;
+    if let Some(status_addr) = args.status_addr {
+        status::serve_status(status_addr, handle.status())?;
+    }
+    if let Some(mut fee_account_collector) = fee_account_collector {
+        // poll the fee account until the engine shuts down
+        let shutdown = handle.shutdown_token();
+        let retry_interval = Duration::from_secs(args.account_poll_interval);
+        tokio::spawn(async move {
+            let poll = async {
+                loop {
+                    match fee_account_collector.get_event_stream().await {
+                        Ok(mut stream) => {
+                            while let Some(event) = stream.next().await {
+                                if let Err(e) = event {
+                                    warn!("Error polling the fee account: {:#}", e);
+                                }
+                            }
+                        }
+                        Err(e) => warn!("Error polling the fee account: {:#}", e),
+                    }
+                    tokio::time::sleep(retry_interval).await;
+                }
+            };
+            tokio::select! {
+                _ = shutdown.cancelled() => {}
+                _ = poll => {}
+            }
+        });
+    }
+    let shutdown = handle.shutdown_token();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("shutdown signal received, stopping engine");
+        shutdown.cancel();
+    });
+    handle.join().await;
     Ok(())
 }
+
+/// Resolves when the process receives SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Unable to install SIGINT handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Unable to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
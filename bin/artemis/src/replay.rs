@@ -0,0 +1,184 @@
+use std::{
+    convert::Infallible,
+    fs::{self, OpenOptions},
+    io::Write,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use artemis_core::types::Executor;
+use async_trait::async_trait;
+use blend_strategies::types::Action;
+use hyper::{
+    body,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+/// A canned JSON-RPC response served by the [RpcStub](RpcStub).
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcFixture {
+    pub method: String,
+    /// The params the fixture answers. Fixtures without params answer any call
+    /// to the method that no fixture matches exactly.
+    #[serde(default)]
+    pub params: Option<Value>,
+    pub result: Value,
+}
+
+/// A JSON-RPC server that answers from a file of [fixtures](RpcFixture), so a replay
+/// never touches the network. Fixtures that match the same call are served in
+/// file order, and the last one keeps being served once the others are used up.
+pub struct RpcStub {
+    fixtures: Vec<RpcFixture>,
+    served: Mutex<Vec<bool>>,
+}
+
+impl RpcStub {
+    /// Loads fixtures from a JSON array.
+    pub fn load(path: &str) -> Result<Self> {
+        let fixtures: Vec<RpcFixture> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(Self {
+            served: Mutex::new(vec![false; fixtures.len()]),
+            fixtures,
+        })
+    }
+
+    /// Starts serving on a local port and returns the url to point RPC clients at.
+    pub fn serve(self) -> Result<String> {
+        let stub = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let stub = stub.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let stub = stub.clone();
+                    async move { Ok::<_, Infallible>(stub.handle(req).await) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?.serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                warn!("rpc stub stopped: {}", e);
+            }
+        });
+        Ok(url)
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let request: Value = match body::to_bytes(req.into_body()).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+            Err(_) => Value::Null,
+        };
+        let method = request["method"].as_str().unwrap_or_default();
+        let response = match self.answer(method, &request["params"]) {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            None => {
+                warn!("no rpc fixture for {} {}", method, request["params"]);
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": { "code": -32601, "message": format!("no fixture for {}", method) },
+                })
+            }
+        };
+        Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::from(response.to_string()))
+            .unwrap_or_default()
+    }
+
+    fn answer(&self, method: &str, params: &Value) -> Option<Value> {
+        let exact: Vec<usize> =
+            self.matching(|f| f.method == method && f.params.as_ref() == Some(params));
+        let candidates = if exact.is_empty() {
+            self.matching(|f| f.method == method && f.params.is_none())
+        } else {
+            exact
+        };
+        let mut served = self.served.lock().unwrap();
+        let index = candidates
+            .iter()
+            .find(|index| !served[**index])
+            .or(candidates.last())?;
+        served[*index] = true;
+        Some(self.fixtures[*index].result.clone())
+    }
+
+    fn matching(&self, matches: impl Fn(&RpcFixture) -> bool) -> Vec<usize> {
+        (0..self.fixtures.len())
+            .filter(|index| matches(&self.fixtures[*index]))
+            .collect()
+    }
+}
+
+/// An executor that logs the actions a replay produces instead of submitting them.
+pub struct ActionLog {
+    path: String,
+}
+
+impl ActionLog {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Executor<Action> for ActionLog {
+    async fn execute(&self, action: Action) -> Result<()> {
+        let Action::SubmitTx(tx) = action;
+        let msg = format!("{} {:?}", tx.action_id, tx.op);
+        info!("replay produced action: {}", msg);
+        let mut output = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(Path::new(&self.path))?;
+        writeln!(output, "{}", msg)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RpcFixture, RpcStub};
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    fn fixture(method: &str, params: Option<Value>, result: Value) -> RpcFixture {
+        RpcFixture {
+            method: method.to_string(),
+            params,
+            result,
+        }
+    }
+
+    #[test]
+    fn test_stub_serves_fixtures_in_order() {
+        let fixtures = vec![
+            fixture("getLatestLedger", None, json!(1)),
+            fixture("getLatestLedger", None, json!(2)),
+            fixture("getLedgerEntries", Some(json!({"keys": ["a"]})), json!("a")),
+            fixture("getLedgerEntries", None, json!("any")),
+        ];
+        let stub = RpcStub {
+            served: Mutex::new(vec![false; fixtures.len()]),
+            fixtures,
+        };
+        assert_eq!(stub.answer("getLatestLedger", &Value::Null), Some(json!(1)));
+        assert_eq!(stub.answer("getLatestLedger", &Value::Null), Some(json!(2)));
+        assert_eq!(stub.answer("getLatestLedger", &Value::Null), Some(json!(2)));
+        let params = json!({"keys": ["a"]});
+        assert_eq!(stub.answer("getLedgerEntries", &params), Some(json!("a")));
+        let params = json!({"keys": ["b"]});
+        assert_eq!(stub.answer("getLedgerEntries", &params), Some(json!("any")));
+        assert_eq!(stub.answer("getEvents", &Value::Null), None);
+    }
+}
@@ -0,0 +1,52 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use anyhow::Result;
+use artemis_core::status::StatusHandle;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use tracing::{info, warn};
+
+/// Serves the engine's [status](StatusHandle) as JSON on `GET /status`.
+pub fn serve_status(addr: SocketAddr, status: StatusHandle) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let status = status.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let status = status.clone();
+                async move { Ok::<_, Infallible>(respond(&status, req)) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!(
+        "serving engine status on http://{}/status",
+        server.local_addr()
+    );
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            warn!("status endpoint stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+fn respond(status: &StatusHandle, req: Request<Body>) -> Response<Body> {
+    if req.uri().path() != "/status" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap_or_default();
+    }
+    match serde_json::to_string(&status.snapshot()) {
+        Ok(body) => Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap_or_default(),
+        Err(e) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string()))
+            .unwrap_or_default(),
+    }
+}
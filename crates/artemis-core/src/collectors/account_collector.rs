@@ -0,0 +1,333 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::types::{Collector, CollectorStream};
+use crate::utilities::backoff::Backoff;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::warn;
+
+/// The reserve every account and subentry locks up, in stroops.
+pub const BASE_RESERVE: i128 = 5_000_000;
+
+/// A classic account as Horizon reports it. Assets are named `native` or
+/// `CODE:ISSUER`, like the stellar asset contracts that wrap them, and amounts
+/// are in stroops.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountState {
+    pub sequence: i64,
+    pub subentry_count: u32,
+    pub num_sponsoring: u32,
+    pub num_sponsored: u32,
+    pub balances: BTreeMap<String, i128>,
+    /// The limit of every trustline.
+    pub limits: BTreeMap<String, i128>,
+    /// The amount of every asset offers are selling.
+    pub selling_liabilities: BTreeMap<String, i128>,
+    /// The trustlines that aren't authorized.
+    pub unauthorized: BTreeSet<String>,
+}
+
+impl AccountState {
+    /// The native balance the account must keep to exist.
+    pub fn min_balance(&self) -> i128 {
+        let entries = 2 + self.subentry_count as i128 + self.num_sponsoring as i128
+            - self.num_sponsored as i128;
+        entries * BASE_RESERVE
+    }
+
+    /// The native balance left to pay fees with.
+    pub fn spendable_balance(&self) -> i128 {
+        self.spendable("native")
+    }
+
+    /// The balance of `asset` the account can spend, less what its offers sell
+    /// and, for the native asset, its minimum balance. Nothing can be spent
+    /// from a trustline that isn't authorized.
+    pub fn spendable(&self, asset: &str) -> i128 {
+        if self.unauthorized.contains(asset) {
+            return 0;
+        }
+        let mut spendable = self.balances.get(asset).copied().unwrap_or(0)
+            - self.selling_liabilities.get(asset).copied().unwrap_or(0);
+        if asset == "native" {
+            spendable -= self.min_balance();
+        }
+        spendable.max(0)
+    }
+}
+
+/// A change to a classic account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountEvent {
+    /// The balance of an asset, or the part of it that can be spent, changed. A
+    /// removed trustline has a zero balance.
+    Balance {
+        asset: String,
+        balance: i128,
+        /// The balance as [AccountState::spendable](AccountState::spendable) reports it.
+        spendable: i128,
+    },
+    /// A trustline's limit changed. A removed trustline has a zero limit.
+    TrustlineLimit {
+        asset: String,
+        limit: i128,
+    },
+    Sequence(i64),
+    Subentries(u32),
+}
+
+/// A collector that polls an account from Horizon, or any server implementing
+/// its `/accounts/{id}` endpoint, and emits an [AccountEvent](AccountEvent) for
+/// everything that changed. Everything but zero amounts is emitted on the first
+/// poll.
+pub struct AccountCollector {
+    horizon_url: String,
+    account_id: String,
+    poll_interval: Duration,
+    /// The most consecutive failed polls retried before the stream fails.
+    max_retries: u32,
+    backoff: Backoff,
+    /// Shared with the polling task, so a restarted stream only emits what changed.
+    state: Arc<watch::Sender<Option<AccountState>>>,
+}
+
+impl AccountCollector {
+    pub fn new(horizon_url: &str, account_id: &str) -> Self {
+        Self {
+            horizon_url: horizon_url.trim_end_matches('/').to_string(),
+            account_id: account_id.to_string(),
+            poll_interval: Duration::from_secs(5),
+            max_retries: 5,
+            backoff: Backoff::default(),
+            state: Arc::new(watch::channel(None).0),
+        }
+    }
+
+    /// Sets how often the account is polled.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Retries up to `max_retries` consecutive failed polls, waiting `backoff`
+    /// between them, before failing the stream.
+    pub fn with_retries(mut self, max_retries: u32, backoff: Backoff) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns a receiver that always holds the latest state of the account, or
+    /// `None` before it was first read.
+    pub fn account(&self) -> watch::Receiver<Option<AccountState>> {
+        self.state.subscribe()
+    }
+}
+
+/// Parses a Horizon amount, a decimal string with up to 7 places, into stroops.
+fn parse_amount(amount: &str) -> Result<i128> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if fraction.len() > 7 {
+        return Err(anyhow!("amount {} has more than 7 decimals", amount));
+    }
+    let fraction = format!("{:0<7}", fraction);
+    Ok(whole.parse::<i128>()? * 10_000_000 + fraction.parse::<i128>()?)
+}
+
+/// Reads the account state from a Horizon `/accounts/{id}` response.
+fn parse_account(res: &Value) -> Result<AccountState> {
+    let count = |field: &str| res[field].as_u64().unwrap_or(0) as u32;
+    let mut state = AccountState {
+        sequence: res["sequence"]
+            .as_str()
+            .ok_or_else(|| anyhow!("account has no sequence"))?
+            .parse()?,
+        subentry_count: count("subentry_count"),
+        num_sponsoring: count("num_sponsoring"),
+        num_sponsored: count("num_sponsored"),
+        ..Default::default()
+    };
+    let balances = res["balances"]
+        .as_array()
+        .ok_or_else(|| anyhow!("account has no balances"))?;
+    for balance in balances {
+        let asset = match balance["asset_type"].as_str() {
+            Some("native") => "native".to_string(),
+            Some("liquidity_pool_shares") => format!(
+                "pool:{}",
+                balance["liquidity_pool_id"].as_str().unwrap_or_default()
+            ),
+            _ => format!(
+                "{}:{}",
+                balance["asset_code"].as_str().unwrap_or_default(),
+                balance["asset_issuer"].as_str().unwrap_or_default()
+            ),
+        };
+        if let Some(amount) = balance["balance"].as_str() {
+            state.balances.insert(asset.clone(), parse_amount(amount)?);
+        }
+        if let Some(selling) = balance["selling_liabilities"].as_str() {
+            state
+                .selling_liabilities
+                .insert(asset.clone(), parse_amount(selling)?);
+        }
+        if balance["is_authorized"].as_bool() == Some(false) {
+            state.unauthorized.insert(asset.clone());
+        }
+        if let Some(limit) = balance["limit"].as_str() {
+            state.limits.insert(asset, parse_amount(limit)?);
+        }
+    }
+    Ok(state)
+}
+
+/// Returns what changed from `old` to `new`.
+fn changes(old: &AccountState, new: &AccountState) -> Vec<AccountEvent> {
+    let mut events = vec![];
+    if old.sequence != new.sequence {
+        events.push(AccountEvent::Sequence(new.sequence));
+    }
+    if old.subentry_count != new.subentry_count {
+        events.push(AccountEvent::Subentries(new.subentry_count));
+    }
+    // a trustline may have a limit before it has a balance entry
+    let assets = |state: &AccountState| {
+        let mut assets: Vec<String> = state.balances.keys().cloned().collect();
+        assets.extend(state.limits.keys().cloned());
+        assets
+    };
+    let mut all_assets = assets(old);
+    all_assets.extend(assets(new));
+    all_assets.sort();
+    all_assets.dedup();
+    for asset in all_assets {
+        let balance = new.balances.get(&asset).copied().unwrap_or(0);
+        let spendable = new.spendable(&asset);
+        if old.balances.get(&asset).copied().unwrap_or(0) != balance
+            || old.spendable(&asset) != spendable
+        {
+            events.push(AccountEvent::Balance {
+                asset: asset.clone(),
+                balance,
+                spendable,
+            });
+        }
+        let limit = new.limits.get(&asset).copied().unwrap_or(0);
+        if old.limits.get(&asset).copied().unwrap_or(0) != limit {
+            events.push(AccountEvent::TrustlineLimit { asset, limit });
+        }
+    }
+    events
+}
+
+#[async_trait]
+impl Collector<AccountEvent> for AccountCollector {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, AccountEvent>> {
+        let (sender, receiver) = mpsc::channel(512);
+        let url = format!("{}/accounts/{}", self.horizon_url, self.account_id);
+        let state = self.state.clone();
+        let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let mut poll = interval(self.poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            let mut attempt = 0;
+            while !sender.is_closed() {
+                poll.tick().await;
+                let new = match fetch_account(&client, &url).await {
+                    Ok(new) => {
+                        attempt = 0;
+                        new
+                    }
+                    Err(e) if attempt < max_retries => {
+                        let delay = backoff.delay(attempt);
+                        warn!("error fetching account: {}, retrying in {:?}", e, delay);
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e)).await;
+                        return;
+                    }
+                };
+                // the first state is compared to an empty account, so everything is emitted
+                let old = state.borrow().clone().unwrap_or_default();
+                state.send_replace(Some(new.clone()));
+                for event in changes(&old, &new) {
+                    if sender.send(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Box::pin(ReceiverStream::new(receiver)))
+    }
+}
+
+async fn fetch_account(client: &reqwest::Client, url: &str) -> Result<AccountState> {
+    let res = client.get(url).send().await?.error_for_status()?;
+    parse_account(&serde_json::from_str(&res.text().await?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changes, parse_account, parse_amount, AccountEvent, AccountState};
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_account() {
+        assert_eq!(parse_amount("12.5").unwrap(), 125_000_000);
+        assert_eq!(parse_amount("0.0000001").unwrap(), 1);
+        assert!(parse_amount("1.00000001").is_err());
+
+        let res = json!({
+            "sequence": "4294967300",
+            "subentry_count": 1,
+            "balances": [
+                { "balance": "100.0000000", "limit": "1000.0000000", "asset_type": "credit_alphanum4", "asset_code": "USDC", "asset_issuer": "GISSUER" },
+                { "balance": "3.0000000", "selling_liabilities": "0.5000000", "asset_type": "native" },
+            ],
+        });
+        let state = parse_account(&res).unwrap();
+        assert_eq!(state.sequence, 4294967300);
+        assert_eq!(state.balances["native"], 30_000_000);
+        assert_eq!(state.limits["USDC:GISSUER"], 10_000_000_000);
+        // 2 base entries plus the trustline, less the 0.5 XLM offers are selling
+        assert_eq!(state.spendable_balance(), 10_000_000);
+
+        let events = changes(&AccountState::default(), &state);
+        assert!(events.contains(&AccountEvent::Sequence(4294967300)));
+        assert!(events.contains(&AccountEvent::Subentries(1)));
+        assert!(events.contains(&AccountEvent::Balance {
+            asset: "native".to_string(),
+            balance: 30_000_000,
+            spendable: 10_000_000,
+        }));
+        assert_eq!(events.len(), 5);
+        assert!(changes(&state, &state).is_empty());
+
+        // a limit is reported even without a balance entry
+        let mut limited = state.clone();
+        limited
+            .limits
+            .insert("EURC:GISSUER".to_string(), 50_000_000);
+        assert_eq!(
+            changes(&state, &limited),
+            vec![AccountEvent::TrustlineLimit {
+                asset: "EURC:GISSUER".to_string(),
+                limit: 50_000_000,
+            }]
+        );
+    }
+}
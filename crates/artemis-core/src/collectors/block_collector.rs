@@ -1,54 +1,267 @@
 use crate::types::{Collector, CollectorStream};
-use anyhow::Result;
+use crate::utilities::{backoff::Backoff, rpc::RpcClient};
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use core::time;
-use std::thread::sleep;
-use stellar_rpc_client::Client;
-use tokio::sync::broadcast::{self};
-use tokio_stream::{wrappers::BroadcastStream, StreamExt};
-// / A collector that listens for new blockchain event logs based on a [Filter](Filter),
-/// and generates a stream of [events](Log).
+use jsonrpsee_core::params::ObjectParams;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use stellar_rpc_client::Error;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::warn;
+
+/// The most ledgers requested from `getLedgers` at once.
+const LEDGER_PAGE_LIMIT: u32 = 200;
+
+/// How many blocks can wait for the engine before polling pauses.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// The JSON-RPC error code of a method the server doesn't have.
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// A collector that polls the RPC for new ledgers and emits a [NewBlock](NewBlock)
+/// for every one of them, in order.
 pub struct BlockCollector {
-    network_url: String,
+    rpc: RpcClient,
     last_block_num: u32,
+    latest_ledger: watch::Sender<u32>,
+    poll_interval: Duration,
+    /// The most consecutive failed polls retried before the stream fails.
+    max_retries: u32,
+    backoff: Backoff,
+    /// Whether the RPC may support `getLedgers`, cleared once it says it doesn't.
+    get_ledgers: Arc<AtomicBool>,
 }
 
 impl BlockCollector {
-    pub fn new(url: String) -> Self {
+    pub fn new(rpc: RpcClient) -> Self {
         Self {
-            network_url: url,
+            rpc,
             last_block_num: 0,
+            latest_ledger: watch::channel(0).0,
+            poll_interval: Duration::from_secs(1),
+            max_retries: 5,
+            backoff: Backoff::default(),
+            get_ledgers: Arc::new(AtomicBool::new(true)),
         }
     }
+
+    /// Sets how often the RPC is polled for new ledgers.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Retries up to `max_retries` consecutive failed polls, waiting `backoff`
+    /// between them, before failing the stream.
+    pub fn with_retries(mut self, max_retries: u32, backoff: Backoff) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns a receiver that always holds the latest ledger the collector has
+    /// seen, or 0 before the first one.
+    pub fn latest_ledger(&self) -> watch::Receiver<u32> {
+        self.latest_ledger.subscribe()
+    }
 }
 
-/// A new block event, containing the block number and hash.
-#[derive(Debug, Clone)]
+/// A new block event. Every ledger after the first one seen is emitted, even if
+/// several closed between two polls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewBlock {
     pub number: u32,
+    /// When the ledger closed, in seconds since the unix epoch, if the RPC
+    /// supports `getLedgers`.
+    #[serde(default)]
+    pub close_time: Option<u64>,
+    /// The protocol version of the latest ledger when the block was polled.
+    #[serde(default)]
+    pub protocol_version: u32,
+}
+
+/// Fetches the close times of the ledgers from `start` to `end` inclusive.
+async fn close_times(client: &RpcClient, start: u32, end: u32) -> Result<HashMap<u32, u64>> {
+    let mut close_times = HashMap::new();
+    let mut next = start;
+    while next <= end {
+        let mut params = ObjectParams::new();
+        params.insert("startLedger", next)?;
+        params.insert(
+            "pagination",
+            json!({ "limit": (end - next + 1).min(LEDGER_PAGE_LIMIT) }),
+        )?;
+        let res: Value = client.request("getLedgers", params).await?;
+        let page = parse_close_times(&res)?;
+        let Some(last) = page.keys().max().copied() else {
+            break;
+        };
+        close_times.extend(page);
+        next = last + 1;
+    }
+    Ok(close_times)
+}
+
+/// Whether a request failed because the RPC doesn't have the method.
+fn is_method_not_found(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<Error>(),
+        Some(Error::JsonRpc(jsonrpsee_core::Error::Call(call))) if call.code() == METHOD_NOT_FOUND
+    )
+}
+
+/// Reads the ledger close times from a `getLedgers` response.
+fn parse_close_times(res: &Value) -> Result<HashMap<u32, u64>> {
+    let ledgers = res["ledgers"]
+        .as_array()
+        .ok_or_else(|| anyhow!("getLedgers response has no ledgers"))?;
+    Ok(ledgers
+        .iter()
+        .filter_map(|ledger| {
+            let sequence = ledger["sequence"].as_u64()? as u32;
+            // the close time is a string of seconds, but accept a number as well
+            let close_time = match &ledger["ledgerCloseTime"] {
+                Value::String(close_time) => close_time.parse().ok()?,
+                close_time => close_time.as_u64()?,
+            };
+            Some((sequence, close_time))
+        })
+        .collect())
 }
+
 #[async_trait]
 impl Collector<NewBlock> for BlockCollector {
-    async fn get_event_stream(&mut self) -> Result<CollectorStream<'_, NewBlock>> {
-        let (sender, receiver) = broadcast::channel(500000);
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, NewBlock>> {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let mut last_block_num = self.last_block_num;
-        let url = self.network_url.clone();
+        let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let server = self.rpc.clone();
+        let get_ledgers = self.get_ledgers.clone();
+        let mut poll = interval(self.poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
         tokio::spawn(async move {
-            let server = Client::new(&url).unwrap();
-
-            loop {
-                let result = server.get_latest_ledger().await.unwrap();
-                if result.sequence > last_block_num {
-                    last_block_num = result.sequence;
-                    let _ = sender.send(NewBlock {
-                        number: result.sequence,
-                    });
+            let mut warned = false;
+            let mut attempt = 0;
+            while !sender.is_closed() {
+                poll.tick().await;
+                let result = match server.get_latest_ledger().await {
+                    Ok(result) => {
+                        attempt = 0;
+                        result
+                    }
+                    Err(e) if attempt < max_retries => {
+                        let delay = backoff.delay(attempt);
+                        warn!(
+                            "error fetching latest ledger: {}, retrying in {:?}",
+                            e, delay
+                        );
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e.into())).await;
+                        return;
+                    }
+                };
+                if result.sequence <= last_block_num {
+                    continue;
                 }
-                sleep(time::Duration::from_secs(1));
+                // start from the latest ledger on the first poll rather than replaying history
+                let first = match last_block_num {
+                    0 => result.sequence,
+                    _ => last_block_num + 1,
+                };
+                let close_times = if !get_ledgers.load(Ordering::Relaxed) {
+                    HashMap::new()
+                } else {
+                    match close_times(&server, first, result.sequence).await {
+                        Ok(close_times) => close_times,
+                        Err(e) if is_method_not_found(&e) => {
+                            warn!("RPC doesn't support getLedgers, blocks have no close times");
+                            get_ledgers.store(false, Ordering::Relaxed);
+                            HashMap::new()
+                        }
+                        Err(e) => {
+                            if !warned {
+                                warn!("unable to fetch ledger close times: {}", e);
+                                warned = true;
+                            }
+                            HashMap::new()
+                        }
+                    }
+                };
+                for number in first..=result.sequence {
+                    let block = NewBlock {
+                        number,
+                        close_time: close_times.get(&number).copied(),
+                        protocol_version: result.protocol_version,
+                    };
+                    if sender.send(Ok(block)).await.is_err() {
+                        return;
+                    }
+                }
+                last_block_num = result.sequence;
+            }
+        });
+        // remember the last block so a restarted stream picks up where this one left off
+        let last_block_num = &mut self.last_block_num;
+        let latest_ledger = &self.latest_ledger;
+        let stream = ReceiverStream::new(receiver).map(move |block: Result<NewBlock>| {
+            if let Ok(block) = &block {
+                *last_block_num = block.number;
+                latest_ledger.send_replace(block.number);
             }
+            block
         });
-        let stream = BroadcastStream::new(receiver);
-        let stream = stream.filter_map(|block| Some(block.unwrap()));
-        Ok(Box::pin(stream)) // don't specify this if I don't have to
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_method_not_found, parse_close_times};
+    use serde_json::json;
+    use stellar_rpc_client::Error;
+
+    #[test]
+    fn test_parse_close_times() {
+        let res = json!({
+            "ledgers": [
+                { "sequence": 10, "ledgerCloseTime": "1700000000" },
+                { "sequence": 11, "ledgerCloseTime": 1700000005 },
+                { "sequence": 12 },
+            ],
+        });
+        let close_times = parse_close_times(&res).unwrap();
+        assert_eq!(close_times.len(), 2);
+        assert_eq!(close_times[&10], 1700000000);
+        assert_eq!(close_times[&11], 1700000005);
+        assert!(parse_close_times(&json!({})).is_err());
+    }
+
+    #[test]
+    fn test_is_method_not_found() {
+        let call = |code: i32| -> anyhow::Error {
+            let error = json!({ "code": code, "message": "error" });
+            Error::JsonRpc(jsonrpsee_core::Error::Call(
+                serde_json::from_value(error).unwrap(),
+            ))
+            .into()
+        };
+        assert!(is_method_not_found(&call(-32601)));
+        assert!(!is_method_not_found(&call(-32602)));
+        assert!(!is_method_not_found(&Error::InvalidResponse.into()));
     }
 }
@@ -0,0 +1,77 @@
+use crate::types::{Collector, CollectorStream};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, Sender};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tracing::warn;
+
+/// Where an executed action ended up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecutionStatus {
+    /// The transaction was included in a ledger and applied successfully.
+    Success,
+    /// The transaction was included in a ledger but failed. The fee was still charged.
+    Failed,
+    /// The transaction never made it into a ledger. It failed simulation, was
+    /// rejected on submission, or timed out.
+    NotIncluded,
+}
+
+/// The outcome of executing an action, fed back to strategies as an event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    /// The id the strategy gave the action that was executed.
+    pub action_id: String,
+    /// The hex encoded transaction hash, if the transaction was submitted.
+    pub tx_hash: Option<String>,
+    pub status: ExecutionStatus,
+    /// The ledger the transaction was included in.
+    pub ledger: Option<u32>,
+    /// The fee charged in stroops.
+    pub fee_charged: Option<i64>,
+    /// Why the action failed, if it did.
+    pub error: Option<String>,
+}
+
+/// The sending half of the execution result channel, handed to executors.
+pub type ExecutionResultSender = Sender<ExecutionResult>;
+
+/// A collector that turns the [results](ExecutionResult) reported by executors into
+/// a stream of events.
+pub struct ExecutionResultCollector {
+    sender: ExecutionResultSender,
+}
+
+impl ExecutionResultCollector {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(512);
+        Self { sender }
+    }
+
+    /// Returns a sender executors can report results through.
+    pub fn sender(&self) -> ExecutionResultSender {
+        self.sender.clone()
+    }
+}
+
+impl Default for ExecutionResultCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Collector<ExecutionResult> for ExecutionResultCollector {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, ExecutionResult>> {
+        let stream = BroadcastStream::new(self.sender.subscribe());
+        let stream = stream.filter_map(|result| match result {
+            Ok(result) => Some(Ok(result)),
+            Err(e) => {
+                warn!("dropped execution results: {}", e);
+                None
+            }
+        });
+        Ok(Box::pin(stream))
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::types::{Collector, CollectorStream};
+use crate::utilities::{backoff::Backoff, rpc::RpcClient};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use stellar_xdr::curr::{LedgerEntryData, LedgerKey, Limits, ReadXdr};
+use tokio::sync::mpsc;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::warn;
+
+/// The most keys requested from `getLedgerEntries` at once.
+pub const KEY_BATCH_LIMIT: usize = 200;
+
+/// How many changes can wait for the engine before polling pauses.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A cloneable handle to the keys a [LedgerEntryCollector](LedgerEntryCollector)
+/// watches. Keys added or removed through any clone are picked up on the next poll.
+#[derive(Debug, Clone, Default)]
+pub struct WatchedKeys {
+    keys: Arc<Mutex<BTreeSet<LedgerKey>>>,
+}
+
+impl WatchedKeys {
+    /// Starts watching `key`. Returns whether it wasn't watched already.
+    pub fn watch(&self, key: LedgerKey) -> bool {
+        self.keys.lock().unwrap().insert(key)
+    }
+
+    /// Stops watching `key`. Returns whether it was watched.
+    pub fn unwatch(&self, key: &LedgerKey) -> bool {
+        self.keys.lock().unwrap().remove(key)
+    }
+
+    /// Returns every watched key.
+    pub fn keys(&self) -> Vec<LedgerKey> {
+        self.keys.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// A watched ledger entry that was created, modified, or removed. An entry seen
+/// for the first time is reported as created.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntryChange {
+    pub key: LedgerKey,
+    pub old_last_modified_ledger: Option<u32>,
+    pub new_last_modified_ledger: Option<u32>,
+    pub old_value: Option<LedgerEntryData>,
+    /// `None` if the entry was removed or, for temporary entries, expired.
+    pub new_value: Option<LedgerEntryData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    last_modified_ledger: u32,
+    value: LedgerEntryData,
+}
+
+/// A collector that polls a dynamic set of ledger entries in batches and emits a
+/// [LedgerEntryChange](LedgerEntryChange) whenever one of them changes.
+pub struct LedgerEntryCollector {
+    rpc: RpcClient,
+    keys: WatchedKeys,
+    poll_interval: Duration,
+    /// The most consecutive failed polls retried before the stream fails.
+    max_retries: u32,
+    backoff: Backoff,
+    /// The last known state of every polled key, `None` if the entry didn't exist.
+    entries: HashMap<LedgerKey, Option<Entry>>,
+}
+
+impl LedgerEntryCollector {
+    pub fn new(rpc: RpcClient) -> Self {
+        Self {
+            rpc,
+            keys: WatchedKeys::default(),
+            poll_interval: Duration::from_secs(5),
+            max_retries: 5,
+            backoff: Backoff::default(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Sets how often the watched entries are polled.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Retries up to `max_retries` consecutive failed polls, waiting `backoff`
+    /// between them, before failing the stream.
+    pub fn with_retries(mut self, max_retries: u32, backoff: Backoff) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns a handle to add and remove the watched keys.
+    pub fn watched_keys(&self) -> WatchedKeys {
+        self.keys.clone()
+    }
+}
+
+/// Fetches the entries of `keys` that exist.
+async fn fetch_entries(rpc: &RpcClient, keys: &[LedgerKey]) -> Result<HashMap<LedgerKey, Entry>> {
+    let mut entries = HashMap::new();
+    for batch in keys.chunks(KEY_BATCH_LIMIT) {
+        let res = rpc.get_ledger_entries(batch).await?;
+        for entry in res.entries.unwrap_or_default() {
+            let key = LedgerKey::from_xdr_base64(&entry.key, Limits::none())?;
+            let value = LedgerEntryData::from_xdr_base64(&entry.xdr, Limits::none())?;
+            entries.insert(
+                key,
+                Entry {
+                    last_modified_ledger: entry.last_modified_ledger,
+                    value,
+                },
+            );
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns the change from the `old` state of `key` to `new`, if there is one.
+/// `old` is `None` if the key was never polled.
+fn diff(
+    key: &LedgerKey,
+    old: Option<&Option<Entry>>,
+    new: Option<&Entry>,
+) -> Option<LedgerEntryChange> {
+    let old = old.cloned().flatten();
+    if old.as_ref() == new {
+        return None;
+    }
+    Some(LedgerEntryChange {
+        key: key.clone(),
+        old_last_modified_ledger: old.as_ref().map(|old| old.last_modified_ledger),
+        new_last_modified_ledger: new.map(|new| new.last_modified_ledger),
+        old_value: old.map(|old| old.value),
+        new_value: new.map(|new| new.value.clone()),
+    })
+}
+
+#[async_trait]
+impl Collector<LedgerEntryChange> for LedgerEntryCollector {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, LedgerEntryChange>> {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let rpc = self.rpc.clone();
+        let watched = self.keys.clone();
+        let mut known = self.entries.clone();
+        let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let mut poll = interval(self.poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        tokio::spawn(async move {
+            let mut attempt = 0;
+            while !sender.is_closed() {
+                poll.tick().await;
+                let keys = watched.keys();
+                // forget unwatched keys so watching them again reports them afresh
+                known.retain(|key, _| keys.contains(key));
+                let entries = match fetch_entries(&rpc, &keys).await {
+                    Ok(entries) => {
+                        attempt = 0;
+                        entries
+                    }
+                    Err(e) if attempt < max_retries => {
+                        let delay = backoff.delay(attempt);
+                        warn!(
+                            "error fetching ledger entries: {}, retrying in {:?}",
+                            e, delay
+                        );
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e)).await;
+                        return;
+                    }
+                };
+                for key in keys {
+                    let new = entries.get(&key);
+                    if let Some(change) = diff(&key, known.get(&key), new) {
+                        if sender.send(Ok(change)).await.is_err() {
+                            return;
+                        }
+                    }
+                    known.insert(key, new.cloned());
+                }
+            }
+        });
+        // remember the entries so a restarted stream only reports what changed since
+        let entries = &mut self.entries;
+        let stream = ReceiverStream::new(receiver).map(move |change: Result<LedgerEntryChange>| {
+            if let Ok(change) = &change {
+                let entry = change
+                    .new_value
+                    .clone()
+                    .zip(change.new_last_modified_ledger)
+                    .map(|(value, last_modified_ledger)| Entry {
+                        last_modified_ledger,
+                        value,
+                    });
+                entries.insert(change.key.clone(), entry);
+            }
+            change
+        });
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Entry};
+    use stellar_xdr::curr::{
+        AccountId, Hash, LedgerEntryData, LedgerKey, LedgerKeyAccount, PublicKey, TtlEntry, Uint256,
+    };
+
+    #[test]
+    fn test_diff() {
+        let key = LedgerKey::Account(LedgerKeyAccount {
+            account_id: AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([0; 32]))),
+        });
+        let entry = |last_modified_ledger| Entry {
+            last_modified_ledger,
+            value: LedgerEntryData::Ttl(TtlEntry {
+                key_hash: Hash([0; 32]),
+                live_until_ledger_seq: 100,
+            }),
+        };
+
+        // a missing entry seen for the first time is not a change
+        assert_eq!(diff(&key, None, None), None);
+        let created = diff(&key, None, Some(&entry(10))).unwrap();
+        assert_eq!(created.old_last_modified_ledger, None);
+        assert_eq!(created.new_last_modified_ledger, Some(10));
+        assert_eq!(diff(&key, Some(&Some(entry(10))), Some(&entry(10))), None);
+        let modified = diff(&key, Some(&Some(entry(10))), Some(&entry(11))).unwrap();
+        assert_eq!(modified.old_last_modified_ledger, Some(10));
+        assert_eq!(modified.new_last_modified_ledger, Some(11));
+        let removed = diff(&key, Some(&Some(entry(11))), None).unwrap();
+        assert_eq!(removed.new_value, None);
+        assert!(removed.old_value.is_some());
+    }
+}
@@ -1,12 +1,24 @@
-use core::time;
-use std::thread::sleep;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use crate::types::{Collector, CollectorStream};
-use anyhow::Result;
+use crate::utilities::{backoff::Backoff, rpc::RpcClient};
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use stellar_rpc_client::{Client, Event, EventStart, EventType, GetEventsResponse};
-use tokio::sync::broadcast;
-use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use jsonrpsee_core::params::ObjectParams;
+use serde::{Deserialize, Serialize};
+use stellar_rpc_client::{Event, EventStart, EventType};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::{info, warn};
+
+/// How long to wait before polling again once the collector has caught up.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug, Clone)]
 pub struct EventFilter {
     pub event_type: EventType,
@@ -16,82 +28,463 @@ pub struct EventFilter {
 /// A collector that listens for new blockchain event logs based on a [Filter](Filter),
 /// and generates a stream of [events](Log).
 pub struct LogCollector {
-    network_url: String,
+    rpc: RpcClient,
     filter: EventFilter,
+    /// Contract ids that replace the filter's while the collector runs.
+    contract_ids: Option<watch::Receiver<Vec<String>>>,
     last_event_timestamp: u32,
     last_curser_id: Option<String>,
+    /// Where the cursor is persisted, if anywhere.
+    cursor_path: Option<PathBuf>,
+    /// The most events requested per page.
+    page_limit: usize,
+    /// The most consecutive failed requests retried before the stream fails.
+    max_retries: u32,
+    backoff: Backoff,
+}
+
+/// An item emitted by the [LogCollector](LogCollector).
+#[derive(Debug, Clone)]
+pub enum LogEvent {
+    Event(Event),
+    /// The cursor fell out of the RPC's retention window and the collector
+    /// skipped ahead, so events may have been missed.
+    Gap(EventGap),
+}
+
+/// The ledgers a [LogCollector](LogCollector) skipped. Events emitted from
+/// `from_ledger` up to, but not including, `to_ledger` may have been missed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventGap {
+    pub from_ledger: u32,
+    pub to_ledger: u32,
 }
 
 impl LogCollector {
-    pub fn new(url: String, filter: EventFilter) -> Self {
+    pub fn new(rpc: RpcClient, filter: EventFilter) -> Self {
         Self {
-            network_url: url,
+            rpc,
             filter,
+            contract_ids: None,
             last_event_timestamp: 0,
             last_curser_id: None,
+            cursor_path: None,
+            page_limit: 100,
+            max_retries: 5,
+            backoff: Backoff::default(),
+        }
+    }
+
+    /// Sets the most events requested per page.
+    pub fn with_page_limit(mut self, page_limit: usize) -> Self {
+        self.page_limit = page_limit.max(1);
+        self
+    }
+
+    /// Retries up to `max_retries` consecutive failed requests, waiting `backoff`
+    /// between them, before failing the stream.
+    pub fn with_retries(mut self, max_retries: u32, backoff: Backoff) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Persists the cursor to `path` once every event has been handed to the
+    /// strategies and resumes from it on startup, so events emitted while the bot
+    /// was down are not missed.
+    pub fn with_cursor_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cursor_path = Some(path.into());
+        self
+    }
+
+    /// Requests the events of the contract ids `contract_ids` holds instead of the
+    /// filter's, so contracts can be added or removed while the collector runs.
+    pub fn with_contract_ids(mut self, contract_ids: watch::Receiver<Vec<String>>) -> Self {
+        self.contract_ids = Some(contract_ids);
+        self
+    }
+}
+
+/// Where a [LogCollector](LogCollector) left off.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LogCursor {
+    ledger: u32,
+    paging_token: Option<String>,
+}
+
+impl LogCursor {
+    /// Reads the cursor at `path`, or `None` if none was saved yet.
+    fn load(path: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
         }
     }
+
+    /// Records the cursor as where the collector left off, persisting it to `path`
+    /// if set.
+    fn remember(self, ledger: &mut u32, paging_token: &mut Option<String>, path: &Option<PathBuf>) {
+        if let Some(path) = path {
+            if let Err(e) = self.save(path) {
+                warn!("error saving log cursor to {:?}: {}", path, e);
+            }
+        }
+        *ledger = self.ledger;
+        *paging_token = self.paging_token;
+    }
+
+    /// Writes the cursor to `path`, replacing the previous one atomically.
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Returns the oldest ledger the RPC still retains events for.
+async fn oldest_ledger(client: &RpcClient) -> Result<u32> {
+    let res: serde_json::Value = client.request("getHealth", ObjectParams::new()).await?;
+    res["oldestLedger"]
+        .as_u64()
+        .map(|ledger| ledger as u32)
+        .ok_or_else(|| anyhow!("getHealth response has no oldestLedger"))
 }
 
 /// Implementation of the [Collector](Collector) trait for the [LogCollector](LogCollector).
 #[async_trait]
-impl Collector<Event> for LogCollector {
-    async fn get_event_stream(&mut self) -> Result<CollectorStream<'_, Event>> {
-        let (sender, receiver) = broadcast::channel(500000);
+impl Collector<LogEvent> for LogCollector {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, LogEvent>> {
+        if let (0, Some(path)) = (self.last_event_timestamp, &self.cursor_path) {
+            match LogCursor::load(path) {
+                Ok(Some(cursor)) => {
+                    info!("resuming log collector from ledger {}", cursor.ledger);
+                    self.last_event_timestamp = cursor.ledger;
+                    self.last_curser_id = cursor.paging_token;
+                }
+                Ok(None) => {}
+                Err(e) => warn!("ignoring unreadable log cursor {:?}: {}", path, e),
+            }
+        }
+        let (sender, receiver) = mpsc::channel(500000);
         let mut last_event_timestamp = self.last_event_timestamp;
         let mut last_cursor_id = self.last_curser_id.clone();
         let filter = self.filter.clone();
-        let network_url = self.network_url.clone();
+        let watched_contract_ids = self.contract_ids.clone();
+        let page_limit = self.page_limit;
+        let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let client = self.rpc.clone();
         tokio::spawn(async move {
-            let client = Client::new(&network_url).unwrap();
             if last_event_timestamp == 0 {
-                last_event_timestamp = client.get_latest_ledger().await.unwrap().sequence;
+                last_event_timestamp = match client.get_latest_ledger().await {
+                    Ok(result) => result.sequence,
+                    Err(e) => {
+                        let _ = sender.send(Err(e.into())).await;
+                        return;
+                    }
+                };
+            } else {
+                // the RPC only keeps recent events, so an old cursor may point past its history
+                match oldest_ledger(&client).await {
+                    Ok(oldest) if oldest > last_event_timestamp => {
+                        let gap = EventGap {
+                            from_ledger: last_event_timestamp,
+                            to_ledger: oldest,
+                        };
+                        warn!("log cursor is out of retention, skipping {:?}", gap);
+                        last_event_timestamp = oldest;
+                        last_cursor_id = None;
+                        let cursor = LogCursor {
+                            ledger: gap.to_ledger,
+                            paging_token: None,
+                        };
+                        let _ = sender.send(Ok((cursor, Some(LogEvent::Gap(gap))))).await;
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("unable to check the oldest retained ledger: {}", e),
+                }
             }
 
-            loop {
-                let result: GetEventsResponse;
-                if let Some(cursor_id) = last_cursor_id.clone() {
-                    result = client
-                        .get_events(
-                            EventStart::Cursor(cursor_id),
-                            Some(EventType::Contract),
-                            filter.contract_ids.as_slice(),
-                            filter.topics.as_slice(),
-                            None,
-                        )
-                        .await
-                        .unwrap();
-                } else {
-                    result = client
-                        .get_events(
-                            EventStart::Ledger(last_event_timestamp),
-                            Some(EventType::Contract),
-                            filter.contract_ids.as_slice(),
-                            filter.topics.as_slice(),
-                            None,
-                        )
-                        .await
-                        .unwrap();
+            let mut attempt = 0;
+            while !sender.is_closed() {
+                let start = match last_cursor_id.clone() {
+                    Some(cursor_id) => EventStart::Cursor(cursor_id),
+                    None => EventStart::Ledger(last_event_timestamp),
+                };
+                let contract_ids = match &watched_contract_ids {
+                    Some(contract_ids) => contract_ids.borrow().clone(),
+                    None => filter.contract_ids.clone(),
+                };
+                let e: anyhow::Error = match client
+                    .get_events(
+                        start,
+                        Some(EventType::Contract),
+                        contract_ids.as_slice(),
+                        filter.topics.as_slice(),
+                        Some(page_limit),
+                    )
+                    .await
+                {
+                    Ok(result) => {
+                        attempt = 0;
+                        let caught_up = result.events.len() < page_limit;
+                        if result.events.is_empty() && result.latest_ledger > last_event_timestamp {
+                            // nothing happened up to the latest ledger, so don't scan it again
+                            last_event_timestamp = result.latest_ledger;
+                            last_cursor_id = None;
+                            let cursor = LogCursor {
+                                ledger: last_event_timestamp,
+                                paging_token: None,
+                            };
+                            let _ = sender.send(Ok((cursor, None))).await;
+                        }
+                        for event in result.events.into_iter() {
+                            last_cursor_id = Some(event.paging_token.clone());
+                            last_event_timestamp = event.ledger;
+                            let cursor = LogCursor {
+                                ledger: event.ledger,
+                                paging_token: last_cursor_id.clone(),
+                            };
+                            let _ = sender
+                                .send(Ok((cursor, Some(LogEvent::Event(event)))))
+                                .await;
+                        }
+                        if caught_up {
+                            tokio::time::sleep(POLL_INTERVAL).await;
+                        }
+                        continue;
+                    }
+                    // the request is rejected once the cursor falls out of retention, which
+                    // only the oldest retained ledger tells apart from other failures
+                    Err(e) => match oldest_ledger(&client).await {
+                        Ok(oldest) if oldest > last_event_timestamp => {
+                            let gap = EventGap {
+                                from_ledger: last_event_timestamp,
+                                to_ledger: oldest,
+                            };
+                            warn!("log cursor is out of retention, skipping {:?}", gap);
+                            last_event_timestamp = oldest;
+                            last_cursor_id = None;
+                            let cursor = LogCursor {
+                                ledger: gap.to_ledger,
+                                paging_token: None,
+                            };
+                            let _ = sender.send(Ok((cursor, Some(LogEvent::Gap(gap))))).await;
+                            continue;
+                        }
+                        _ => e.into(),
+                    },
+                };
+                if attempt >= max_retries {
+                    let _ = sender.send(Err(e)).await;
+                    return;
                 }
-
-                if result.events.len() > 0 {
-                    for event in result.events.into_iter() {
-                        let _ = sender.send(event.clone());
-                        last_cursor_id = Some(event.paging_token);
-                        last_event_timestamp = event.ledger;
+                let delay = backoff.delay(attempt);
+                warn!("error fetching events: {}, retrying in {:?}", e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        });
+        // remember the cursor so a restarted stream picks up where this one left off,
+        // but only once the engine asks for the next event, after it dispatched the
+        // previous one, so a crash never skips an event that wasn't handed over
+        let last_event_timestamp = &mut self.last_event_timestamp;
+        let last_cursor_id = &mut self.last_curser_id;
+        let cursor_path = &self.cursor_path;
+        let mut pending: Option<LogCursor> = None;
+        let stream = ReceiverStream::new(receiver).filter_map(
+            move |item: Result<(LogCursor, Option<LogEvent>)>| {
+                if let Some(delivered) = pending.take() {
+                    delivered.remember(last_event_timestamp, last_cursor_id, cursor_path);
+                }
+                let (cursor, event) = match item {
+                    Ok(item) => item,
+                    Err(e) => return Some(Err(e)),
+                };
+                match event {
+                    Some(LogEvent::Event(event)) if event.event_type != "contract" => {
+                        cursor.remember(last_event_timestamp, last_cursor_id, cursor_path);
+                        None
+                    }
+                    Some(event) => {
+                        pending = Some(cursor);
+                        Some(Ok(event))
+                    }
+                    // the collector moved past ledgers without events
+                    None => {
+                        cursor.remember(last_event_timestamp, last_cursor_id, cursor_path);
+                        None
                     }
                 }
-                sleep(time::Duration::from_secs(1));
+            },
+        );
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventFilter, LogCollector, LogCursor, LogEvent};
+    use crate::types::Collector;
+    use crate::utilities::{backoff::Backoff, rpc::RpcClient};
+    use hyper::{
+        body,
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use serde_json::{json, Value};
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use stellar_rpc_client::EventType;
+    use tokio_stream::StreamExt;
+
+    /// Serves JSON-RPC requests from `answer`, which returns a result or an error
+    /// object for a method and its params, and returns the url to request.
+    fn serve_rpc<F>(answer: F) -> String
+    where
+        F: Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static,
+    {
+        let answer = Arc::new(answer);
+        let make_service = make_service_fn(move |_| {
+            let answer = answer.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let answer = answer.clone();
+                    async move {
+                        let bytes = body::to_bytes(req.into_body()).await.unwrap_or_default();
+                        let request: Value = serde_json::from_slice(&bytes).unwrap_or_default();
+                        let method = request["method"].as_str().unwrap_or_default();
+                        let response = match answer(method, &request["params"]) {
+                            Ok(result) => {
+                                json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+                            }
+                            Err(error) => {
+                                json!({ "jsonrpc": "2.0", "id": request["id"], "error": error })
+                            }
+                        };
+                        Ok::<_, Infallible>(Response::new(Body::from(response.to_string())))
+                    }
+                }))
             }
         });
-        let stream = BroadcastStream::new(receiver);
-        let stream = stream.filter_map(|event| {
-            if event.clone().unwrap().event_type == "contract" {
-                Some(event.unwrap())
-            } else {
-                None
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    fn event(ledger: u32, paging_token: &str) -> Value {
+        json!({
+            "type": "contract",
+            "ledger": ledger,
+            "ledgerClosedAt": "2024-01-01T00:00:00Z",
+            "id": paging_token,
+            "pagingToken": paging_token,
+            "contractId": "CA",
+            "topic": [],
+            "value": "AAAAAQ==",
+        })
+    }
+
+    fn error() -> Value {
+        json!({ "code": -32600, "message": "boom" })
+    }
+
+    /// A collector resuming from ledger 10, retrying failures right away.
+    fn collector(url: &str, max_retries: u32) -> LogCollector {
+        let filter = EventFilter {
+            event_type: EventType::Contract,
+            contract_ids: vec!["CA".to_string()],
+            topics: vec![],
+        };
+        let mut collector = LogCollector::new(RpcClient::new(&[url]).unwrap(), filter)
+            .with_page_limit(2)
+            .with_retries(max_retries, Backoff::new(Duration::ZERO, Duration::ZERO));
+        collector.last_event_timestamp = 10;
+        collector
+    }
+
+    #[tokio::test]
+    async fn test_pages_through_events() {
+        let url = serve_rpc(|method, params| match method {
+            "getHealth" => Ok(json!({ "oldestLedger": 1 })),
+            "getEvents" => {
+                let events = match params["pagination"]["cursor"].as_str() {
+                    None => vec![event(10, "1"), event(11, "2")],
+                    Some("2") => vec![event(12, "3")],
+                    Some(_) => vec![],
+                };
+                Ok(json!({ "events": events, "latestLedger": 12 }))
             }
+            _ => Err(error()),
         });
-        Ok(Box::pin(stream))
+        let mut collector = collector(&url, 0);
+        let mut stream = collector.get_event_stream().await.unwrap();
+        for paging_token in ["1", "2", "3"] {
+            match stream.next().await {
+                Some(Ok(LogEvent::Event(event))) => assert_eq!(event.paging_token, paging_token),
+                other => panic!("expected event {}, got {:?}", paging_token, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gap_when_out_of_retention() {
+        let health_checks = AtomicU32::new(0);
+        let url = serve_rpc(move |method, _| match method {
+            // retained on startup, but not by the time events are requested
+            "getHealth" => match health_checks.fetch_add(1, Ordering::SeqCst) {
+                0 => Ok(json!({ "oldestLedger": 5 })),
+                _ => Ok(json!({ "oldestLedger": 50 })),
+            },
+            _ => Err(error()),
+        });
+        let mut collector = collector(&url, 0);
+        let mut stream = collector.get_event_stream().await.unwrap();
+        match stream.next().await {
+            Some(Ok(LogEvent::Gap(gap))) => {
+                assert_eq!((gap.from_ledger, gap.to_ledger), (10, 50));
+            }
+            other => panic!("expected a gap, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fails_once_retries_are_exhausted() {
+        let requests = Arc::new(AtomicU32::new(0));
+        let counted = requests.clone();
+        let url = serve_rpc(move |method, _| match method {
+            "getHealth" => Ok(json!({ "oldestLedger": 1 })),
+            _ => {
+                counted.fetch_add(1, Ordering::SeqCst);
+                Err(error())
+            }
+        });
+        let mut collector = collector(&url, 2);
+        let mut stream = collector.get_event_stream().await.unwrap();
+        assert!(matches!(stream.next().await, Some(Err(_))));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let path = std::env::temp_dir().join(format!("log_cursor_{}.json", std::process::id()));
+        assert_eq!(LogCursor::load(&path).unwrap(), None);
+        let cursor = LogCursor {
+            ledger: 42,
+            paging_token: Some("0000180388626432-0000000001".to_string()),
+        };
+        cursor.save(&path).unwrap();
+        assert_eq!(LogCursor::load(&path).unwrap(), Some(cursor));
+        std::fs::remove_file(path).unwrap();
     }
 }
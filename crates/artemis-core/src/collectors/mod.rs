@@ -7,3 +7,15 @@ pub mod block_collector;
 
 /// This collector listens to a stream of new event logs.
 pub mod log_collector;
+
+/// This collector watches a set of ledger entries for changes.
+pub mod ledger_entry_collector;
+
+/// This collector emits the results executors report for the actions they execute.
+pub mod execution_result_collector;
+
+/// This collector replays the events of a recording.
+pub mod replay_collector;
+
+/// This collector polls a classic account from Horizon for changes.
+pub mod account_collector;
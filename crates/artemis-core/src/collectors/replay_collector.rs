@@ -0,0 +1,45 @@
+use std::marker::PhantomData;
+
+use crate::recorder::read_recording;
+use crate::types::{Collector, CollectorStream};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use tokio_stream::{self as stream, StreamExt};
+
+/// A collector that replays the events of a recording written by a
+/// [FileRecorder](crate::recorder::FileRecorder), in order and as fast as they
+/// are consumed. The stream ends after the last recorded event.
+pub struct ReplayCollector<E> {
+    path: String,
+    /// Index of the next event to replay, so a restarted stream resumes.
+    next: usize,
+    _event: PhantomData<fn() -> E>,
+}
+
+impl<E> ReplayCollector<E> {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            next: 0,
+            _event: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<E> Collector<E> for ReplayCollector<E>
+where
+    E: DeserializeOwned + Send + 'static,
+{
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, E>> {
+        let events = read_recording::<E>(&self.path)?;
+        let skip = self.next;
+        let next = &mut self.next;
+        let stream = stream::iter(events.into_iter().skip(skip)).map(move |recorded| {
+            *next += 1;
+            Ok(recorded.event)
+        });
+        Ok(Box::pin(stream))
+    }
+}
@@ -0,0 +1,252 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+
+/// How the [Engine](crate::engine::Engine) delivers events or actions to a component.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Delivery {
+    /// Receive from a dedicated broadcast channel of the dispatcher's broadcast
+    /// capacity. Producers never wait: a subscriber that falls more than the
+    /// capacity behind skips its oldest items and is told how many it missed,
+    /// without affecting other subscribers.
+    #[default]
+    Broadcast,
+    /// Receive from a dedicated bounded channel. Producers wait for the subscriber
+    /// to make room, so nothing is dropped.
+    Lossless { capacity: usize },
+}
+
+/// Why a [Subscription](Subscription) failed to return the next item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryError {
+    /// Every producer is gone and all queued items were received.
+    Closed,
+    /// The subscriber fell behind and this many items were skipped.
+    Lagged(u64),
+}
+
+/// The receiving end of a component's channel.
+pub struct Subscription<T> {
+    receiver: Receiver<T>,
+    /// Items sent to the subscriber and not yet received or skipped.
+    queued: Arc<AtomicUsize>,
+}
+
+enum Receiver<T> {
+    Broadcast(broadcast::Receiver<T>),
+    Lossless(mpsc::Receiver<T>),
+}
+
+impl<T: Clone> Subscription<T> {
+    /// Receives the next item.
+    pub async fn recv(&mut self) -> Result<T, DeliveryError> {
+        let res = match &mut self.receiver {
+            Receiver::Broadcast(receiver) => match receiver.recv().await {
+                Ok(item) => Ok(item),
+                Err(RecvError::Closed) => Err(DeliveryError::Closed),
+                Err(RecvError::Lagged(skipped)) => Err(DeliveryError::Lagged(skipped)),
+            },
+            Receiver::Lossless(receiver) => receiver.recv().await.ok_or(DeliveryError::Closed),
+        };
+        let taken = match res {
+            Ok(_) => 1,
+            Err(DeliveryError::Lagged(skipped)) => skipped as usize,
+            Err(DeliveryError::Closed) => 0,
+        };
+        // the dispatcher counts an item before sending it, so this never underflows
+        self.queued.fetch_sub(taken, Ordering::Relaxed);
+        res
+    }
+
+    /// The number of items waiting to be received.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// A counter that follows [queued](Subscription::queued) after the
+    /// subscription has moved to its component.
+    pub(crate) fn queued_counter(&self) -> Arc<AtomicUsize> {
+        self.queued.clone()
+    }
+}
+
+/// Which items a subscriber wants to receive. Items are only cloned into the
+/// channels of subscribers interested in them.
+pub struct Interest<T> {
+    filter: Option<Filter<T>>,
+}
+
+/// A predicate deciding whether a subscriber receives an item.
+type Filter<T> = Arc<dyn Fn(&T) -> bool + Send + Sync>;
+
+impl<T> Interest<T> {
+    /// Receive every item.
+    pub fn all() -> Self {
+        Self { filter: None }
+    }
+
+    /// Receive the items `filter` accepts.
+    pub fn matching(filter: impl Fn(&T) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            filter: Some(Arc::new(filter)),
+        }
+    }
+
+    pub fn matches(&self, item: &T) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter(item))
+    }
+}
+
+impl<T> Clone for Interest<T> {
+    fn clone(&self) -> Self {
+        Self {
+            filter: self.filter.clone(),
+        }
+    }
+}
+
+impl<T> Default for Interest<T> {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// The sending end of a subscriber's channel.
+#[derive(Clone)]
+enum Sender<T> {
+    Broadcast(broadcast::Sender<T>),
+    Lossless(mpsc::Sender<T>),
+}
+
+/// A subscriber as seen by the [Dispatcher](Dispatcher).
+#[derive(Clone)]
+struct Subscriber<T> {
+    interest: Interest<T>,
+    sender: Sender<T>,
+    queued: Arc<AtomicUsize>,
+}
+
+/// Fans items out to the subscribers interested in them. Subscribers see their
+/// channel close once every clone of the dispatcher has been dropped.
+#[derive(Clone)]
+pub struct Dispatcher<T> {
+    broadcast_capacity: usize,
+    subscribers: Vec<Subscriber<T>>,
+}
+
+impl<T: Clone> Dispatcher<T> {
+    pub fn new(broadcast_capacity: usize) -> Self {
+        Self {
+            broadcast_capacity,
+            subscribers: vec![],
+        }
+    }
+
+    /// Creates a subscription to every item with the given delivery semantics.
+    /// Subscriptions must be created before the dispatcher is cloned to producers.
+    pub fn subscribe(&mut self, delivery: Delivery) -> Subscription<T> {
+        self.subscribe_with_interest(delivery, Interest::all())
+    }
+
+    /// Creates a subscription to the items `interest` matches.
+    pub fn subscribe_with_interest(
+        &mut self,
+        delivery: Delivery,
+        interest: Interest<T>,
+    ) -> Subscription<T> {
+        let (sender, receiver) = match delivery {
+            Delivery::Broadcast => {
+                let (sender, receiver) = broadcast::channel(self.broadcast_capacity);
+                (Sender::Broadcast(sender), Receiver::Broadcast(receiver))
+            }
+            Delivery::Lossless { capacity } => {
+                let (sender, receiver) = mpsc::channel(capacity);
+                (Sender::Lossless(sender), Receiver::Lossless(receiver))
+            }
+        };
+        let queued = Arc::new(AtomicUsize::new(0));
+        self.subscribers.push(Subscriber {
+            interest,
+            sender,
+            queued: queued.clone(),
+        });
+        Subscription { receiver, queued }
+    }
+
+    /// Sends an item to every interested subscriber, waiting for room in lossless
+    /// channels. Returns the number of subscribers that could not be reached.
+    pub async fn send(&self, item: T) -> usize {
+        let mut failed = 0;
+        for subscriber in self.subscribers.iter() {
+            if !subscriber.interest.matches(&item) {
+                continue;
+            }
+            subscriber.queued.fetch_add(1, Ordering::Relaxed);
+            let sent = match &subscriber.sender {
+                Sender::Broadcast(sender) => sender.send(item.clone()).is_ok(),
+                Sender::Lossless(sender) => sender.send(item.clone()).await.is_ok(),
+            };
+            if !sent {
+                subscriber.queued.fetch_sub(1, Ordering::Relaxed);
+                failed += 1;
+            }
+        }
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delivery, DeliveryError, Dispatcher, Interest};
+
+    #[tokio::test]
+    async fn test_lossless_subscriber_receives_everything() {
+        let mut dispatcher = Dispatcher::new(2);
+        let mut lossless = dispatcher.subscribe(Delivery::Lossless { capacity: 8 });
+        let mut broadcast = dispatcher.subscribe(Delivery::Broadcast);
+        for i in 0..5 {
+            assert_eq!(dispatcher.send(i).await, 0);
+        }
+        drop(dispatcher);
+        assert_eq!(lossless.queued(), 5);
+        assert_eq!(broadcast.queued(), 5);
+
+        for i in 0..5 {
+            assert_eq!(lossless.recv().await, Ok(i));
+        }
+        assert_eq!(lossless.recv().await, Err(DeliveryError::Closed));
+
+        assert_eq!(lossless.queued(), 0);
+
+        assert_eq!(broadcast.recv().await, Err(DeliveryError::Lagged(3)));
+        assert_eq!(broadcast.queued(), 2);
+        assert_eq!(broadcast.recv().await, Ok(3));
+        assert_eq!(broadcast.recv().await, Ok(4));
+        assert_eq!(broadcast.recv().await, Err(DeliveryError::Closed));
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_only_receive_matching_items() {
+        let mut dispatcher = Dispatcher::new(8);
+        let mut large = dispatcher.subscribe_with_interest(
+            Delivery::Lossless { capacity: 8 },
+            Interest::matching(|i: &u32| *i >= 2),
+        );
+        let mut all = dispatcher.subscribe(Delivery::Broadcast);
+        for i in 0..4 {
+            assert_eq!(dispatcher.send(i).await, 0);
+        }
+        drop(dispatcher);
+
+        assert_eq!(large.recv().await, Ok(2));
+        assert_eq!(large.recv().await, Ok(3));
+        assert_eq!(large.recv().await, Err(DeliveryError::Closed));
+        for i in 0..4 {
+            assert_eq!(all.recv().await, Ok(i));
+        }
+    }
+}
@@ -1,9 +1,103 @@
-use tokio::sync::broadcast::{self, Sender};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use futures::FutureExt;
 use tokio::task::JoinSet;
+use tokio::time::{interval_at, Instant};
 use tokio_stream::StreamExt;
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
+use crate::delivery::{Delivery, DeliveryError, Dispatcher, Subscription};
+use crate::recorder::Recorder;
+use crate::status::{ComponentKind, ComponentState, StatusCell, StatusHandle};
+use crate::supervisor::{RestartPolicy, Supervisor};
 use crate::types::{Collector, Executor, Strategy};
+use crate::utilities::{
+    backoff::Backoff,
+    notifier::{notify, Notifier},
+};
+
+/// What the engine does when a strategy fails to [sync its state](Strategy::sync_state)
+/// on startup.
+#[derive(Debug, Clone)]
+pub enum SyncPolicy {
+    /// Retry the sync with exponential backoff. The engine fails to start if the
+    /// strategy still hasn't synced after `max_attempts`.
+    Retry { max_attempts: u32, backoff: Backoff },
+    /// Fail to start the engine as soon as the strategy fails to sync.
+    Abort,
+    /// Start the strategy with whatever state it has and retry the sync every
+    /// `resync_interval` until it succeeds.
+    Degraded { resync_interval: Duration },
+}
+
+impl Default for SyncPolicy {
+    fn default() -> Self {
+        SyncPolicy::Degraded {
+            resync_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Per-strategy settings used by the [Engine](Engine).
+#[derive(Debug, Clone, Default)]
+pub struct StrategyConfig {
+    /// How events are delivered to the strategy.
+    pub delivery: Delivery,
+    /// What to do if the strategy fails to sync on startup.
+    pub sync_policy: SyncPolicy,
+}
+
+impl StrategyConfig {
+    pub fn with_delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.sync_policy = sync_policy;
+        self
+    }
+}
+
+/// Per-executor settings used by the [Engine](Engine).
+#[derive(Debug, Clone)]
+pub struct ExecutorConfig {
+    /// How actions are delivered to the executor.
+    pub delivery: Delivery,
+    /// The most actions the executor runs at once. Actions with the same
+    /// [ordering key](Executor::ordering_key) still run one after another.
+    pub concurrency: usize,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            delivery: Delivery::Broadcast,
+            concurrency: 1,
+        }
+    }
+}
+
+impl ExecutorConfig {
+    pub fn with_delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+}
 
 /// The main engine of Artemis. This struct is responsible for orchestrating the
 /// data flow between collectors, strategies, and executors.
@@ -11,17 +105,31 @@ pub struct Engine<E, A> {
     /// The set of collectors that the engine will use to collect events.
     collectors: Vec<Box<dyn Collector<E>>>,
 
-    /// The set of strategies that the engine will use to process events.
-    strategies: Vec<Box<dyn Strategy<E, A>>>,
+    /// The set of strategies that the engine will use to process events, and the
+    /// settings for each of them.
+    strategies: Vec<(Box<dyn Strategy<E, A>>, StrategyConfig)>,
 
-    /// The set of executors that the engine will use to execute actions.
-    executors: Vec<Box<dyn Executor<A>>>,
+    /// The set of executors that the engine will use to execute actions, and the
+    /// settings for each of them.
+    executors: Vec<(Box<dyn Executor<A>>, ExecutorConfig)>,
 
     /// The capacity of the event channel.
     event_channel_capacity: usize,
 
     /// The capacity of the action channel.
     action_channel_capacity: usize,
+
+    /// How long executors may keep draining actions once shutdown is requested.
+    shutdown_timeout: Duration,
+
+    /// Where the engine sends operator alerts.
+    notifier: Option<Arc<dyn Notifier>>,
+
+    /// How failed collectors are restarted and failed executors paused.
+    restart_policy: RestartPolicy,
+
+    /// Where every event is recorded, if anywhere.
+    recorder: Option<Box<dyn Recorder<E>>>,
 }
 
 impl<E, A> Engine<E, A> {
@@ -32,6 +140,10 @@ impl<E, A> Engine<E, A> {
             executors: vec![],
             event_channel_capacity: 512,
             action_channel_capacity: 512,
+            shutdown_timeout: Duration::from_secs(30),
+            notifier: None,
+            restart_policy: RestartPolicy::default(),
+            recorder: None,
         }
     }
 
@@ -44,6 +156,28 @@ impl<E, A> Engine<E, A> {
         self.action_channel_capacity = capacity;
         self
     }
+
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Records every event the collectors emit. The recorder receives events
+    /// losslessly, so a slow recorder slows down the collectors.
+    pub fn with_recorder(mut self, recorder: Box<dyn Recorder<E>>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
 }
 
 impl<E, A> Default for Engine<E, A> {
@@ -52,6 +186,42 @@ impl<E, A> Default for Engine<E, A> {
     }
 }
 
+/// A handle to a running [Engine](Engine), returned by [run](Engine::run).
+///
+/// Shutdown happens in stages: collectors stop as soon as shutdown is requested,
+/// strategies finish the events already queued and run their
+/// [on_shutdown](Strategy::on_shutdown) hook, and executors drain the remaining
+/// actions until the engine's shutdown timeout expires.
+pub struct EngineHandle {
+    set: JoinSet<()>,
+    shutdown: CancellationToken,
+    status: StatusHandle,
+}
+
+impl EngineHandle {
+    /// Requests a graceful shutdown of the engine.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Returns a token that triggers a graceful shutdown when cancelled.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Returns a handle to the status of every collector, strategy, and executor.
+    pub fn status(&self) -> StatusHandle {
+        self.status.clone()
+    }
+
+    /// Waits for every component of the engine to finish.
+    pub async fn join(mut self) {
+        while let Some(res) = self.set.join_next().await {
+            info!("res: {:?}", res);
+        }
+    }
+}
+
 impl<E, A> Engine<E, A>
 where
     E: Send + Clone + 'static + std::fmt::Debug,
@@ -64,82 +234,593 @@ where
 
     /// Adds a strategy to be used by the engine.
     pub fn add_strategy(&mut self, strategy: Box<dyn Strategy<E, A>>) {
-        self.strategies.push(strategy);
+        self.add_strategy_with_config(strategy, StrategyConfig::default());
+    }
+
+    /// Adds a strategy with its own delivery and sync settings.
+    pub fn add_strategy_with_config(
+        &mut self,
+        strategy: Box<dyn Strategy<E, A>>,
+        config: StrategyConfig,
+    ) {
+        self.strategies.push((strategy, config));
     }
 
     /// Adds an executor to be used by the engine.
     pub fn add_executor(&mut self, executor: Box<dyn Executor<A>>) {
-        self.executors.push(executor);
+        self.add_executor_with_config(executor, ExecutorConfig::default());
+    }
+
+    /// Adds an executor with its own delivery and concurrency settings.
+    pub fn add_executor_with_config(
+        &mut self,
+        executor: Box<dyn Executor<A>>,
+        config: ExecutorConfig,
+    ) {
+        self.executors.push((executor, config));
     }
 
     /// The core run loop of the engine. This function will spawn a thread for
     /// each collector, strategy, and executor. It will then orchestrate the
     /// data flow between them.
-    pub async fn run(self) -> Result<JoinSet<()>, Box<dyn std::error::Error>> {
-        let (event_sender, _): (Sender<E>, _) = broadcast::channel(self.event_channel_capacity);
-        let (action_sender, _): (Sender<A>, _) = broadcast::channel(self.action_channel_capacity);
+    pub async fn run(self) -> Result<EngineHandle, Box<dyn std::error::Error>> {
+        let mut event_dispatcher: Dispatcher<E> = Dispatcher::new(self.event_channel_capacity);
+        let mut action_dispatcher: Dispatcher<A> = Dispatcher::new(self.action_channel_capacity);
+
+        // Subscribe every component before the dispatchers are handed to producers.
+        let executors: Vec<_> = self
+            .executors
+            .into_iter()
+            .map(|(executor, config)| {
+                let receiver = action_dispatcher.subscribe(config.delivery);
+                (executor, config.concurrency, receiver)
+            })
+            .collect::<Vec<_>>();
+        let strategies: Vec<_> = self
+            .strategies
+            .into_iter()
+            .map(|(strategy, config)| {
+                let receiver =
+                    event_dispatcher.subscribe_with_interest(config.delivery, strategy.interest());
+                (strategy, config.sync_policy, receiver)
+            })
+            .collect();
+        let recorder = self.recorder.map(|recorder| {
+            let receiver = event_dispatcher.subscribe(Delivery::Lossless {
+                capacity: self.event_channel_capacity,
+            });
+            (recorder, receiver)
+        });
 
+        let collector_status: Vec<_> = (0..self.collectors.len())
+            .map(|index| StatusCell::new(ComponentKind::Collector, index, None))
+            .collect();
+        let strategy_status: Vec<_> = strategies
+            .iter()
+            .enumerate()
+            .map(|(index, (_, _, receiver))| {
+                StatusCell::new(
+                    ComponentKind::Strategy,
+                    index,
+                    Some(receiver.queued_counter()),
+                )
+            })
+            .collect();
+        let executor_status: Vec<_> = executors
+            .iter()
+            .enumerate()
+            .map(|(index, (_, _, receiver))| {
+                StatusCell::new(
+                    ComponentKind::Executor,
+                    index,
+                    Some(receiver.queued_counter()),
+                )
+            })
+            .collect();
+        let status = StatusHandle::new(
+            [&collector_status, &strategy_status, &executor_status]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+        );
+
+        let shutdown = CancellationToken::new();
         let mut set = JoinSet::new();
 
-        // Spawn executors in separate threads.
-        for executor in self.executors {
-            let mut receiver = action_sender.subscribe();
+        // Spawn the recorder in a separate thread.
+        if let Some((mut recorder, mut receiver)) = recorder {
             set.spawn(async move {
-                info!("starting executor... ");
-                loop {
-                    match receiver.recv().await {
-                        Ok(action) => match executor.execute(action).await {
-                            Ok(_) => {}
-                            Err(e) => error!("error executing action: {}", e),
-                        },
-                        Err(e) => error!("error receiving action: {}", e),
+                info!("starting recorder... ");
+                while let Ok(event) = receiver.recv().await {
+                    if let Err(e) = recorder.record(&event) {
+                        error!("error recording event: {}", e);
                     }
                 }
+                info!("recorder stopped");
             });
         }
 
+        // Spawn executors in separate threads.
+        for (index, ((executor, concurrency, receiver), status)) in
+            executors.into_iter().zip(executor_status).enumerate()
+        {
+            let shutdown = shutdown.clone();
+            let shutdown_timeout = self.shutdown_timeout;
+            // resolves once shutdown was requested and the drain window has passed
+            let deadline = async move {
+                shutdown.cancelled().await;
+                tokio::time::sleep(shutdown_timeout).await;
+            };
+            let supervisor = Supervisor::new(
+                format!("executor {}", index),
+                self.restart_policy,
+                status.clone(),
+                self.notifier.clone(),
+            )
+            .pausing();
+            set.spawn(run_executor(
+                Arc::from(executor),
+                concurrency,
+                receiver,
+                deadline,
+                supervisor,
+                status,
+            ));
+        }
+
         // Spawn strategies in separate threads.
-        for mut strategy in self.strategies {
-            let mut event_receiver = event_sender.subscribe();
-            let action_sender = action_sender.clone();
-            match strategy.sync_state().await {
-                Ok(_) => (),
-                Err(e) => error!("error syncing state: {}", e),
+        for (index, ((mut strategy, sync_policy, mut event_receiver), status)) in
+            strategies.into_iter().zip(strategy_status).enumerate()
+        {
+            let action_dispatcher = action_dispatcher.clone();
+            let notifier = self.notifier.clone();
+            let mut degraded =
+                initial_sync(strategy.as_mut(), index, &sync_policy, &notifier).await?;
+            if degraded {
+                status.error(&"failed to sync state on startup");
+                status.set_state(ComponentState::Degraded);
             }
+            let resync_interval = match sync_policy {
+                SyncPolicy::Degraded { resync_interval } => resync_interval,
+                _ => Duration::from_secs(60),
+            };
 
             set.spawn(async move {
                 info!("starting strategy... ");
+                let mut resync = interval_at(Instant::now() + resync_interval, resync_interval);
                 loop {
-                    match event_receiver.recv().await {
+                    let event = tokio::select! {
+                        _ = resync.tick(), if degraded => {
+                            match strategy.sync_state().await {
+                                Ok(_) => {
+                                    degraded = false;
+                                    status.set_state(ComponentState::Running);
+                                    let msg = format!("strategy {} recovered and synced state", index);
+                                    info!("{}", msg);
+                                    notify(&notifier, &msg).await;
+                                }
+                                Err(e) => {
+                                    warn!("strategy {} is still degraded: {}", index, e);
+                                    status.error(&e);
+                                }
+                            }
+                            continue;
+                        }
+                        event = event_receiver.recv() => event,
+                    };
+                    match event {
                         Ok(event) => {
+                            status.event();
                             for action in strategy.process_event(event).await {
-                                match action_sender.send(action) {
-                                    Ok(_) => {}
-                                    Err(e) => error!("error sending action: {}", e),
+                                status.action();
+                                let failed = action_dispatcher.send(action).await;
+                                if failed > 0 {
+                                    error!("error sending action to {} executors", failed);
                                 }
                             }
                         }
-                        Err(e) => error!("error receiving event: {}", e),
+                        // all collectors have stopped and the queued events are processed
+                        Err(DeliveryError::Closed) => break,
+                        Err(DeliveryError::Lagged(skipped)) => {
+                            warn!("strategy lagged behind, skipped {} events", skipped);
+                            status.error(&format!("lagged behind, skipped {} events", skipped));
+                            if let Err(e) = strategy.on_lag(skipped).await {
+                                error!("error recovering from lag: {}", e);
+                                status.error(&e);
+                            }
+                        }
                     }
                 }
+                if let Err(e) = strategy.on_shutdown().await {
+                    error!("error shutting down strategy: {}", e);
+                    status.error(&e);
+                }
+                status.set_state(ComponentState::Stopped);
+                info!("strategy stopped");
             });
         }
 
         // Spawn collectors in separate threads.
-        for mut collector in self.collectors {
-            let event_sender = event_sender.clone();
+        for (index, (mut collector, status)) in self
+            .collectors
+            .into_iter()
+            .zip(collector_status)
+            .enumerate()
+        {
+            let event_dispatcher = event_dispatcher.clone();
+            let shutdown = shutdown.clone();
+            let mut supervisor = Supervisor::new(
+                format!("collector {}", index),
+                self.restart_policy,
+                status.clone(),
+                self.notifier.clone(),
+            );
             set.spawn(async move {
                 info!("starting collector... ");
-                let mut event_stream = collector.get_event_stream().await.unwrap();
-                while let Some(event) = event_stream.next().await {
-                    match event_sender.send(event) {
-                        Ok(_) => {}
-                        Err(e) => error!("error sending event: {}", e),
+                loop {
+                    let e = match run_collector(
+                        collector.as_mut(),
+                        &event_dispatcher,
+                        &shutdown,
+                        &status,
+                    )
+                    .await
+                    {
+                        Ok(_) => break,
+                        Err(e) => e,
+                    };
+                    let Some(delay) = supervisor.on_failure(&e).await else {
+                        break;
+                    };
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => supervisor.restarted(),
+                        _ = shutdown.cancelled() => break,
                     }
                 }
+                status.set_state(ComponentState::Stopped);
+                info!("collector stopped");
             });
         }
 
-        Ok(set)
+        Ok(EngineHandle {
+            set,
+            shutdown,
+            status,
+        })
+    }
+}
+
+/// Runs up to `concurrency` actions at once until the action channel closes or
+/// `deadline` resolves. Actions that share an [ordering key](Executor::ordering_key)
+/// run in the order they arrived. The executor can't be rebuilt, so a panicking
+/// action pauses it from taking new actions instead, and once the restart
+/// budget is spent it stops taking them for good, finishing those in flight.
+async fn run_executor<A: Send + Clone + 'static>(
+    executor: Arc<dyn Executor<A>>,
+    concurrency: usize,
+    mut receiver: Subscription<A>,
+    deadline: impl Future<Output = ()>,
+    mut supervisor: Supervisor,
+    status: StatusCell,
+) {
+    info!("starting executor... ");
+    tokio::pin!(deadline);
+    let mut running = JoinSet::new();
+    // ordering keys with an action in flight, and the actions waiting on them
+    let mut busy: HashSet<String> = HashSet::new();
+    let mut waiting: HashMap<String, VecDeque<A>> = HashMap::new();
+    let mut closed = false;
+    let mut paused_until = None;
+    while !closed || !running.is_empty() {
+        let pause = async {
+            match paused_until {
+                Some(until) => tokio::time::sleep_until(until).await,
+                None => std::future::pending().await,
+            }
+        };
+        let res = tokio::select! {
+            biased;
+            _ = &mut deadline => {
+                if !running.is_empty() {
+                    warn!("shutdown timeout reached, abandoning in-flight actions");
+                }
+                break;
+            }
+            Some(res) = running.join_next() => res,
+            _ = pause => {
+                paused_until = None;
+                supervisor.resumed();
+                continue;
+            }
+            action = receiver.recv(), if !closed && paused_until.is_none() && running.len() < concurrency => {
+                match action {
+                    Ok(action) => {
+                        status.event();
+                        match executor.ordering_key(&action) {
+                            Some(key) if busy.contains(&key) => {
+                                waiting.entry(key).or_default().push_back(action);
+                            }
+                            Some(key) => {
+                                busy.insert(key.clone());
+                                spawn_execution(&mut running, &executor, action, Some(key));
+                            }
+                            None => spawn_execution(&mut running, &executor, action, None),
+                        }
+                    }
+                    Err(DeliveryError::Closed) => closed = true,
+                    Err(DeliveryError::Lagged(skipped)) => {
+                        warn!("executor lagged behind, skipped {} actions", skipped);
+                        status.error(&format!("lagged behind, skipped {} actions", skipped));
+                    }
+                }
+                continue;
+            }
+        };
+
+        // executions only fail to join when aborted, which happens on drop
+        let Ok((key, res)) = res else { continue };
+        if let Some(key) = key {
+            match waiting.get_mut(&key).and_then(|queue| queue.pop_front()) {
+                Some(action) => spawn_execution(&mut running, &executor, action, Some(key)),
+                None => {
+                    waiting.remove(&key);
+                    busy.remove(&key);
+                }
+            }
+        }
+        let e = match res {
+            Ok(Ok(_)) => {
+                status.action();
+                continue;
+            }
+            Ok(Err(e)) => {
+                error!("error executing action: {}", e);
+                status.error(&e);
+                continue;
+            }
+            Err(payload) => format!("panicked: {}", panic_message(payload)),
+        };
+        match supervisor.on_failure(&e).await {
+            // in-flight actions keep running while the executor is paused
+            Some(delay) => paused_until = Some(tokio::time::Instant::now() + delay),
+            None => {
+                closed = true;
+                let dropped: usize = waiting.values().map(|queue| queue.len()).sum();
+                if dropped > 0 {
+                    warn!("executor stopping, dropped {} waiting actions", dropped);
+                }
+                waiting.clear();
+            }
+        }
+    }
+    status.set_state(ComponentState::Stopped);
+    info!("executor stopped");
+}
+
+/// The outcome of an action run by [spawn_execution](spawn_execution), tagged with
+/// the action's ordering key.
+type Execution = (
+    Option<String>,
+    Result<anyhow::Result<()>, Box<dyn Any + Send>>,
+);
+
+/// Runs an action on its own task, catching panics so a crash doesn't take the
+/// executor down with it.
+fn spawn_execution<A: Send + 'static>(
+    running: &mut JoinSet<Execution>,
+    executor: &Arc<dyn Executor<A>>,
+    action: A,
+    key: Option<String>,
+) {
+    let executor = executor.clone();
+    running.spawn(async move {
+        let res = AssertUnwindSafe(executor.execute(action))
+            .catch_unwind()
+            .await;
+        (key, res)
+    });
+}
+
+/// Extracts the message from a panic payload.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+/// Forwards a collector's events to the dispatcher until shutdown is requested or
+/// the stream ends. Returns an error if the collector failed.
+async fn run_collector<E: Clone>(
+    collector: &mut dyn Collector<E>,
+    event_dispatcher: &Dispatcher<E>,
+    shutdown: &CancellationToken,
+    status: &StatusCell,
+) -> anyhow::Result<()> {
+    let mut event_stream = collector.get_event_stream().await?;
+    loop {
+        let event = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            event = event_stream.next() => event,
+        };
+        match event {
+            Some(event) => {
+                let event = event?;
+                status.event();
+                let failed = event_dispatcher.send(event).await;
+                if failed > 0 {
+                    error!("error sending event to {} strategies", failed);
+                }
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Runs a strategy's startup sync according to its [SyncPolicy](SyncPolicy).
+/// Returns whether the strategy starts degraded, or an error if the engine
+/// should not start.
+async fn initial_sync<E, A>(
+    strategy: &mut dyn Strategy<E, A>,
+    index: usize,
+    policy: &SyncPolicy,
+    notifier: &Option<Arc<dyn Notifier>>,
+) -> anyhow::Result<bool> {
+    let mut attempt = 0;
+    loop {
+        let e = match strategy.sync_state().await {
+            Ok(_) => return Ok(false),
+            Err(e) => e,
+        };
+        match policy {
+            SyncPolicy::Retry {
+                max_attempts,
+                backoff,
+            } if attempt + 1 < *max_attempts => {
+                let delay = backoff.delay(attempt);
+                warn!(
+                    "error syncing state for strategy {}: {}, retrying in {:?}",
+                    index, e, delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            SyncPolicy::Degraded { resync_interval } => {
+                let msg = format!(
+                    "strategy {} failed to sync state: {}, starting degraded and resyncing every {:?}",
+                    index, e, resync_interval
+                );
+                error!("{}", msg);
+                notify(notifier, &msg).await;
+                return Ok(true);
+            }
+            _ => {
+                let msg = format!(
+                    "strategy {} failed to sync state: {}, aborting engine",
+                    index, e
+                );
+                error!("{}", msg);
+                notify(notifier, &msg).await;
+                return Err(anyhow!(msg));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_executor;
+    use crate::delivery::{Delivery, Dispatcher};
+    use crate::status::{ComponentKind, ComponentState, StatusCell, StatusHandle};
+    use crate::supervisor::{RestartPolicy, Supervisor};
+    use crate::types::Executor;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Records when each `(key, id)` action starts and finishes.
+    struct RecordingExecutor {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Executor<(&'static str, u32)> for RecordingExecutor {
+        async fn execute(&self, (key, id): (&'static str, u32)) -> Result<()> {
+            if key == "panic" {
+                panic!("boom");
+            }
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("start {}{}", key, id));
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.log.lock().unwrap().push(format!("end {}{}", key, id));
+            Ok(())
+        }
+
+        fn ordering_key(&self, (key, _): &(&'static str, u32)) -> Option<String> {
+            Some(key.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_execution_keeps_key_order() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut dispatcher = Dispatcher::new(8);
+        let receiver = dispatcher.subscribe(Delivery::Lossless { capacity: 8 });
+        for action in [("a", 1), ("a", 2), ("b", 1)] {
+            dispatcher.send(action).await;
+        }
+        drop(dispatcher);
+        let status = StatusCell::new(ComponentKind::Executor, 0, Some(receiver.queued_counter()));
+        let handle = StatusHandle::new(vec![status.clone()]);
+        assert_eq!(handle.snapshot().components[0].queue_depth, 3);
+
+        let supervisor = Supervisor::new(
+            "executor 0".to_string(),
+            RestartPolicy::default(),
+            status.clone(),
+            None,
+        );
+        run_executor(
+            Arc::new(RecordingExecutor { log: log.clone() }),
+            2,
+            receiver,
+            std::future::pending(),
+            supervisor,
+            status,
+        )
+        .await;
+        let status = handle.snapshot().components.remove(0);
+        assert_eq!(
+            (status.events, status.actions, status.queue_depth),
+            (3, 3, 0)
+        );
+        assert_eq!(status.state, ComponentState::Stopped);
+
+        let log = log.lock().unwrap().clone();
+        let position = |entry: &str| log.iter().position(|e| e == entry).unwrap();
+        assert_eq!(log.len(), 6);
+        // b1 runs alongside a1, while a2 waits for a1 to finish
+        assert!(position("start b1") < position("end a1"));
+        assert!(position("end a1") < position("start a2"));
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_budget_finishes_in_flight_actions() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut dispatcher = Dispatcher::new(8);
+        let receiver = dispatcher.subscribe(Delivery::Lossless { capacity: 8 });
+        for action in [("panic", 1), ("a", 1), ("b", 1)] {
+            dispatcher.send(action).await;
+        }
+        drop(dispatcher);
+        let status = StatusCell::new(ComponentKind::Executor, 0, None);
+        let handle = StatusHandle::new(vec![status.clone()]);
+        let policy = RestartPolicy {
+            max_restarts: 0,
+            ..RestartPolicy::default()
+        };
+        let supervisor =
+            Supervisor::new("executor 0".to_string(), policy, status.clone(), None).pausing();
+        run_executor(
+            Arc::new(RecordingExecutor { log: log.clone() }),
+            2,
+            receiver,
+            std::future::pending(),
+            supervisor,
+            status,
+        )
+        .await;
+        let status = handle.snapshot().components.remove(0);
+        assert_eq!((status.actions, status.state), (1, ComponentState::Stopped));
+        // a1 was in flight when the executor gave up, and b1 never started
+        let log = log.lock().unwrap().clone();
+        assert_eq!(log, vec!["start a1".to_string(), "end a1".to_string()]);
     }
 }
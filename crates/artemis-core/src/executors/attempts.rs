@@ -0,0 +1,82 @@
+use crate::collectors::execution_result_collector::{ExecutionResult, ExecutionStatus};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use stellar_xdr::curr::{Limits, ReadXdr, TransactionMeta, WriteXdr};
+
+/// One attempt at submitting an action as a transaction. An action that is
+/// retried has an attempt for every try.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxAttempt {
+    pub action_id: String,
+    /// The number of the attempt, starting at 0.
+    pub attempt: u32,
+    /// When the attempt started, in seconds since the unix epoch.
+    pub submitted_at: u64,
+    /// `None` if the attempt failed before a transaction was sent.
+    pub tx_hash: Option<String>,
+    pub status: ExecutionStatus,
+    pub ledger: Option<u32>,
+    /// The fee the transaction offered, in stroops.
+    pub fee_bid: Option<u32>,
+    pub fee_charged: Option<i64>,
+    /// The base64 encoded `TransactionResult`, once the transaction was included.
+    pub result_xdr: Option<String>,
+    /// The decoded result code, e.g. `TxSuccess` or `TxFailed`.
+    pub result: Option<String>,
+    /// The base64 encoded contract events the transaction emitted.
+    pub events: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl TxAttempt {
+    /// An attempt that failed before a transaction could be sent.
+    pub fn unsent(action_id: &str, attempt: u32, submitted_at: u64, error: String) -> Self {
+        Self {
+            action_id: action_id.to_string(),
+            attempt,
+            submitted_at,
+            tx_hash: None,
+            status: ExecutionStatus::NotIncluded,
+            ledger: None,
+            fee_bid: None,
+            fee_charged: None,
+            result_xdr: None,
+            result: None,
+            events: vec![],
+            error: Some(error),
+        }
+    }
+
+    /// Returns the result reported for the action if this attempt is its last.
+    pub fn execution_result(&self) -> ExecutionResult {
+        ExecutionResult {
+            action_id: self.action_id.clone(),
+            tx_hash: self.tx_hash.clone(),
+            status: self.status.clone(),
+            ledger: self.ledger,
+            fee_charged: self.fee_charged,
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// Reads the contract events from a base64 encoded `TransactionMeta`.
+pub fn contract_events(result_meta_xdr: &str) -> Result<Vec<String>> {
+    let events = match TransactionMeta::from_xdr_base64(result_meta_xdr, Limits::none())? {
+        TransactionMeta::V3(meta) => meta
+            .soroban_meta
+            .map(|soroban_meta| soroban_meta.events.to_vec())
+            .unwrap_or_default(),
+        _ => vec![],
+    };
+    Ok(events
+        .iter()
+        .map(|event| event.to_xdr_base64(Limits::none()))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Somewhere to keep every [TxAttempt](TxAttempt) an executor makes.
+pub trait AttemptStore: Send + Sync {
+    /// Record an attempt.
+    fn record(&self, attempt: &TxAttempt) -> Result<()>;
+}
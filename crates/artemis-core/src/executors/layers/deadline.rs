@@ -0,0 +1,135 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::watch;
+use tracing::warn;
+
+use super::{report_dropped, Layer};
+use crate::collectors::execution_result_collector::ExecutionResultSender;
+use crate::executors::soroban_executor::SubmitStellarTx;
+use crate::types::Executor;
+
+/// A [Layer](Layer) that drops a transaction once the latest ledger is past its
+/// [target ledger](SubmitStellarTx::target_ledger).
+pub struct DeadlineLayer {
+    latest_ledger: watch::Receiver<u32>,
+    result_sender: Option<ExecutionResultSender>,
+}
+
+impl DeadlineLayer {
+    pub fn new(latest_ledger: watch::Receiver<u32>) -> Self {
+        Self {
+            latest_ledger,
+            result_sender: None,
+        }
+    }
+
+    /// Reports dropped transactions as not included through `sender`.
+    pub fn with_result_sender(mut self, sender: ExecutionResultSender) -> Self {
+        self.result_sender = Some(sender);
+        self
+    }
+}
+
+impl Layer<SubmitStellarTx> for DeadlineLayer {
+    fn layer(
+        &self,
+        inner: Box<dyn Executor<SubmitStellarTx>>,
+    ) -> Box<dyn Executor<SubmitStellarTx>> {
+        Box::new(Deadline {
+            inner,
+            latest_ledger: self.latest_ledger.clone(),
+            result_sender: self.result_sender.clone(),
+        })
+    }
+}
+
+struct Deadline {
+    inner: Box<dyn Executor<SubmitStellarTx>>,
+    latest_ledger: watch::Receiver<u32>,
+    result_sender: Option<ExecutionResultSender>,
+}
+
+#[async_trait]
+impl Executor<SubmitStellarTx> for Deadline {
+    async fn execute(&self, action: SubmitStellarTx) -> Result<()> {
+        let latest_ledger = *self.latest_ledger.borrow();
+        if let Some(target_ledger) = action.target_ledger {
+            if latest_ledger > target_ledger {
+                warn!(
+                    "dropping tx {} targeting ledger {} at ledger {}",
+                    action.action_id, target_ledger, latest_ledger
+                );
+                let error = format!(
+                    "target ledger {} passed at ledger {}",
+                    target_ledger, latest_ledger
+                );
+                report_dropped(&self.result_sender, &action.action_id, error);
+                return Ok(());
+            }
+        }
+        self.inner.execute(action).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeadlineLayer;
+    use crate::collectors::execution_result_collector::{
+        ExecutionResultCollector, ExecutionStatus,
+    };
+    use crate::executors::{layers::Layer, soroban_executor::SubmitStellarTx};
+    use crate::types::Executor;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use ed25519_dalek::SigningKey;
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+    use stellar_xdr::curr::{Operation, OperationBody};
+    use tokio::sync::watch;
+
+    /// Counts the actions it's asked to execute.
+    struct Counter(Arc<AtomicU32>);
+
+    #[async_trait]
+    impl Executor<SubmitStellarTx> for Counter {
+        async fn execute(&self, _action: SubmitStellarTx) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn submit(action_id: &str, target_ledger: u32) -> SubmitStellarTx {
+        SubmitStellarTx {
+            action_id: action_id.to_string(),
+            op: Operation {
+                source_account: None,
+                body: OperationBody::Inflation,
+            },
+            gas_bid_info: None,
+            signing_key: SigningKey::from_bytes(&[0; 32]),
+            max_retries: 0,
+            target_ledger: Some(target_ledger),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_dropped_late_actions() {
+        let results = ExecutionResultCollector::new();
+        let mut received = results.sender().subscribe();
+        let (_, latest_ledger) = watch::channel(100);
+        let executed = Arc::new(AtomicU32::new(0));
+        let deadline = DeadlineLayer::new(latest_ledger)
+            .with_result_sender(results.sender())
+            .layer(Box::new(Counter(executed.clone())));
+
+        deadline.execute(submit("on time", 100)).await.unwrap();
+        deadline.execute(submit("late", 99)).await.unwrap();
+        assert_eq!(executed.load(Ordering::SeqCst), 1);
+        let result = received.try_recv().unwrap();
+        assert_eq!(result.action_id, "late");
+        assert_eq!(result.status, ExecutionStatus::NotIncluded);
+        assert!(result.error.unwrap().contains("target ledger 99"));
+    }
+}
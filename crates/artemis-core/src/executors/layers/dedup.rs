@@ -0,0 +1,165 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use stellar_xdr::curr::{Limits, WriteXdr};
+use tokio::sync::watch;
+use tracing::info;
+
+use super::{report_dropped, Layer};
+use crate::collectors::execution_result_collector::ExecutionResultSender;
+use crate::executors::soroban_executor::SubmitStellarTx;
+use crate::types::Executor;
+
+/// A [Layer](Layer) that drops a transaction if the same contract call, with the
+/// same arguments, was submitted within the last `ledgers` ledgers. Calls that
+/// fail to execute are forgotten so they can be retried right away.
+pub struct DedupLayer {
+    ledgers: u32,
+    latest_ledger: watch::Receiver<u32>,
+    result_sender: Option<ExecutionResultSender>,
+}
+
+impl DedupLayer {
+    pub fn new(ledgers: u32, latest_ledger: watch::Receiver<u32>) -> Self {
+        Self {
+            ledgers,
+            latest_ledger,
+            result_sender: None,
+        }
+    }
+
+    /// Reports dropped transactions as not included through `sender`.
+    pub fn with_result_sender(mut self, sender: ExecutionResultSender) -> Self {
+        self.result_sender = Some(sender);
+        self
+    }
+}
+
+impl Layer<SubmitStellarTx> for DedupLayer {
+    fn layer(
+        &self,
+        inner: Box<dyn Executor<SubmitStellarTx>>,
+    ) -> Box<dyn Executor<SubmitStellarTx>> {
+        Box::new(Dedup {
+            inner,
+            ledgers: self.ledgers,
+            latest_ledger: self.latest_ledger.clone(),
+            result_sender: self.result_sender.clone(),
+            submitted: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+struct Dedup {
+    inner: Box<dyn Executor<SubmitStellarTx>>,
+    ledgers: u32,
+    latest_ledger: watch::Receiver<u32>,
+    result_sender: Option<ExecutionResultSender>,
+    /// The ledger each call was last submitted in, keyed by the encoded call.
+    submitted: Mutex<HashMap<String, u32>>,
+}
+
+#[async_trait]
+impl Executor<SubmitStellarTx> for Dedup {
+    async fn execute(&self, action: SubmitStellarTx) -> Result<()> {
+        let key = match action
+            .contract_call()
+            .and_then(|call| call.to_xdr_base64(Limits::none()).ok())
+        {
+            Some(key) => key,
+            None => return self.inner.execute(action).await,
+        };
+        let ledger = *self.latest_ledger.borrow();
+        {
+            let mut submitted = self.submitted.lock().unwrap();
+            submitted.retain(|_, seen| ledger.saturating_sub(*seen) < self.ledgers);
+            if let Some(seen) = submitted.get(&key) {
+                info!("dropping duplicate tx: {}", action.action_id);
+                let error = format!("duplicate of a call submitted in ledger {}", seen);
+                report_dropped(&self.result_sender, &action.action_id, error);
+                return Ok(());
+            }
+            submitted.insert(key.clone(), ledger);
+        }
+        let res = self.inner.execute(action).await;
+        if res.is_err() {
+            self.submitted.lock().unwrap().remove(&key);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DedupLayer;
+    use crate::collectors::execution_result_collector::{
+        ExecutionResultCollector, ExecutionStatus,
+    };
+    use crate::executors::{layers::Layer, soroban_executor::SubmitStellarTx};
+    use crate::types::Executor;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use ed25519_dalek::SigningKey;
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+    use stellar_xdr::curr::{
+        Hash, HostFunction, InvokeContractArgs, InvokeHostFunctionOp, Operation, OperationBody,
+        ScAddress, ScSymbol, VecM,
+    };
+    use tokio::sync::watch;
+
+    /// Counts the actions it's asked to execute.
+    struct Counter(Arc<AtomicU32>);
+
+    #[async_trait]
+    impl Executor<SubmitStellarTx> for Counter {
+        async fn execute(&self, _action: SubmitStellarTx) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn submit(action_id: &str) -> SubmitStellarTx {
+        let call = InvokeContractArgs {
+            contract_address: ScAddress::Contract(Hash([0; 32])),
+            function_name: ScSymbol("submit".try_into().unwrap()),
+            args: VecM::default(),
+        };
+        SubmitStellarTx {
+            action_id: action_id.to_string(),
+            op: Operation {
+                source_account: None,
+                body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                    host_function: HostFunction::InvokeContract(call),
+                    auth: VecM::default(),
+                }),
+            },
+            gas_bid_info: None,
+            signing_key: SigningKey::from_bytes(&[0; 32]),
+            max_retries: 0,
+            target_ledger: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_dropped_duplicates() {
+        let results = ExecutionResultCollector::new();
+        let mut received = results.sender().subscribe();
+        let (_, latest_ledger) = watch::channel(100);
+        let executed = Arc::new(AtomicU32::new(0));
+        let dedup = DedupLayer::new(10, latest_ledger)
+            .with_result_sender(results.sender())
+            .layer(Box::new(Counter(executed.clone())));
+
+        dedup.execute(submit("first")).await.unwrap();
+        dedup.execute(submit("second")).await.unwrap();
+        assert_eq!(executed.load(Ordering::SeqCst), 1);
+        let result = received.try_recv().unwrap();
+        assert_eq!(result.action_id, "second");
+        assert_eq!(result.status, ExecutionStatus::NotIncluded);
+        assert!(result.error.unwrap().contains("ledger 100"));
+    }
+}
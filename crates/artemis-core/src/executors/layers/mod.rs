@@ -0,0 +1,74 @@
+//! Layers wrap an [Executor](Executor) to add behavior around the actions it
+//! runs, such as dropping or delaying them. Layers are composed into an
+//! [ExecutorStack](ExecutorStack) so they can be configured by the binary
+//! instead of being hard-coded in strategies.
+
+use crate::collectors::execution_result_collector::{
+    ExecutionResult, ExecutionResultSender, ExecutionStatus,
+};
+use crate::types::Executor;
+
+/// This layer drops repeated contract calls.
+pub mod dedup;
+
+/// This layer drops actions whose target ledger has passed.
+pub mod deadline;
+
+/// This layer limits how often each pool is called.
+pub mod rate_limit;
+
+/// Layer trait, which wraps an executor in another executor.
+pub trait Layer<A> {
+    /// Wrap `inner`, returning the executor actions should be sent to.
+    fn layer(&self, inner: Box<dyn Executor<A>>) -> Box<dyn Executor<A>>;
+}
+
+/// Builds an executor wrapped in a stack of [layers](Layer). Actions pass
+/// through the layers in the order they were added.
+pub struct ExecutorStack<A> {
+    layers: Vec<Box<dyn Layer<A>>>,
+}
+
+impl<A> ExecutorStack<A> {
+    pub fn new() -> Self {
+        Self { layers: vec![] }
+    }
+
+    /// Adds a layer below the ones already added.
+    pub fn layer(mut self, layer: impl Layer<A> + 'static) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Wraps `executor` in every layer of the stack.
+    pub fn build(self, executor: Box<dyn Executor<A>>) -> Box<dyn Executor<A>> {
+        self.layers
+            .iter()
+            .rev()
+            .fold(executor, |inner, layer| layer.layer(inner))
+    }
+}
+
+/// Reports an action a layer dropped as not included, with `error` saying why, so
+/// the strategy that sent it isn't left waiting for its result.
+fn report_dropped(sender: &Option<ExecutionResultSender>, action_id: &str, error: String) {
+    let Some(sender) = sender else {
+        return;
+    };
+    let result = ExecutionResult {
+        action_id: action_id.to_string(),
+        tx_hash: None,
+        status: ExecutionStatus::NotIncluded,
+        ledger: None,
+        fee_charged: None,
+        error: Some(error),
+    };
+    // nobody may be listening for results, which is fine for a dropped action
+    let _ = sender.send(result);
+}
+
+impl<A> Default for ExecutorStack<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::info;
+
+use super::Layer;
+use crate::executors::soroban_executor::SubmitStellarTx;
+use crate::types::Executor;
+
+/// A [Layer](Layer) that submits at most `max_calls` transactions to each pool
+/// contract per `window`. Transactions over the limit wait for a free slot.
+pub struct RateLimitLayer {
+    max_calls: usize,
+    window: Duration,
+}
+
+impl RateLimitLayer {
+    pub fn new(max_calls: usize, window: Duration) -> Self {
+        Self {
+            max_calls: max_calls.max(1),
+            window,
+        }
+    }
+}
+
+impl Layer<SubmitStellarTx> for RateLimitLayer {
+    fn layer(
+        &self,
+        inner: Box<dyn Executor<SubmitStellarTx>>,
+    ) -> Box<dyn Executor<SubmitStellarTx>> {
+        Box::new(RateLimit {
+            inner,
+            max_calls: self.max_calls,
+            window: self.window,
+            calls: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+struct RateLimit {
+    inner: Box<dyn Executor<SubmitStellarTx>>,
+    max_calls: usize,
+    window: Duration,
+    /// When recent calls to each pool were let through.
+    calls: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimit {
+    /// Takes a slot for `pool` at `now`, or returns how long to wait for one.
+    fn acquire(&self, pool: &str, now: Instant) -> Option<Duration> {
+        let mut calls = self.calls.lock().unwrap();
+        let calls = calls.entry(pool.to_string()).or_default();
+        while calls
+            .front()
+            .is_some_and(|call| now.duration_since(*call) >= self.window)
+        {
+            calls.pop_front();
+        }
+        match calls.front() {
+            Some(oldest) if calls.len() >= self.max_calls => Some(*oldest + self.window - now),
+            _ => {
+                calls.push_back(now);
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Executor<SubmitStellarTx> for RateLimit {
+    async fn execute(&self, action: SubmitStellarTx) -> Result<()> {
+        if let Some(call) = action.contract_call() {
+            let pool = call.contract_address.to_string();
+            while let Some(wait) = self.acquire(&pool, Instant::now()) {
+                info!("rate limiting tx {} for {:?}", action.action_id, wait);
+                tokio::time::sleep(wait).await;
+            }
+        }
+        self.inner.execute(action).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimit;
+    use crate::executors::soroban_executor::SubmitStellarTx;
+    use crate::types::Executor;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::{collections::HashMap, sync::Mutex, time::Duration};
+    use tokio::time::Instant;
+
+    struct Noop;
+
+    #[async_trait]
+    impl Executor<SubmitStellarTx> for Noop {
+        async fn execute(&self, _action: SubmitStellarTx) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_acquire_limits_each_pool() {
+        let limit = RateLimit {
+            inner: Box::new(Noop),
+            max_calls: 2,
+            window: Duration::from_secs(10),
+            calls: Mutex::new(HashMap::new()),
+        };
+        let start = Instant::now();
+        assert_eq!(limit.acquire("pool_a", start), None);
+        assert_eq!(
+            limit.acquire("pool_a", start + Duration::from_secs(4)),
+            None
+        );
+        assert_eq!(
+            limit.acquire("pool_a", start + Duration::from_secs(5)),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            limit.acquire("pool_b", start + Duration::from_secs(5)),
+            None
+        );
+        assert_eq!(
+            limit.acquire("pool_a", start + Duration::from_secs(10)),
+            None
+        );
+    }
+}
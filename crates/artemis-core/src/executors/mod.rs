@@ -4,3 +4,12 @@
 
 /// This executor submits transactions to stellar.
 pub mod soroban_executor;
+
+/// This executor simulates transactions and records them instead of submitting them.
+pub mod simulating_executor;
+
+/// This module defines the attempts executors make at submitting transactions.
+pub mod attempts;
+
+/// This module contains middleware layers that wrap executors.
+pub mod layers;
@@ -0,0 +1,149 @@
+use crate::collectors::execution_result_collector::ExecutionStatus;
+use crate::executors::soroban_executor::{max_fee, unsigned_transaction, SubmitStellarTx};
+use crate::types::Executor;
+use crate::utilities::{fee_policy::FeePolicy, rpc::RpcClient};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use stellar_rpc_client::Assembled;
+use stellar_xdr::curr::{
+    Limits, Transaction, TransactionEnvelope, TransactionV1Envelope, VecM, WriteXdr,
+};
+use tracing::info;
+
+/// A transaction the [SimulatingExecutor](SimulatingExecutor) would have sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaperTrade {
+    pub action_id: String,
+    /// The contract the transaction calls, if it invokes one.
+    pub contract_id: Option<String>,
+    pub function: Option<String>,
+    pub target_ledger: Option<u32>,
+    /// The ledger the transaction was simulated against.
+    pub latest_ledger: u32,
+    /// When the transaction was simulated, in seconds since the unix epoch.
+    pub simulated_at: u64,
+    /// The assembled transaction, unsigned and without a sequence number, if
+    /// simulation succeeded.
+    pub tx_xdr: Option<String>,
+    pub min_resource_fee: u64,
+    pub cpu_insns: u64,
+    pub mem_bytes: u64,
+    /// The fee that would have been bid, if the fee policy allowed one.
+    pub fee: Option<u32>,
+    /// `Success` if the transaction would have been sent and applied, `Failed`
+    /// if simulation failed or no fee could be bid.
+    pub status: ExecutionStatus,
+    pub error: Option<String>,
+}
+
+/// An executor that assembles and simulates transactions like the
+/// [SorobanExecutor](crate::executors::soroban_executor::SorobanExecutor), but
+/// never sends them. Every would-be transaction is appended to a file as a JSON
+/// [PaperTrade](PaperTrade), so paper fills can be compared to the fills that
+/// happened on chain.
+pub struct SimulatingExecutor {
+    rpc: RpcClient,
+    path: PathBuf,
+    fee_policy: FeePolicy,
+}
+
+impl SimulatingExecutor {
+    /// Creates an executor that records paper trades to `path`.
+    pub fn new(rpc: RpcClient, path: impl AsRef<Path>) -> Self {
+        Self {
+            rpc,
+            path: path.as_ref().to_path_buf(),
+            fee_policy: FeePolicy::default(),
+        }
+    }
+
+    /// Bid fees according to `fee_policy` instead of the default one.
+    pub fn with_fee_policy(mut self, fee_policy: FeePolicy) -> Self {
+        self.fee_policy = fee_policy;
+        self
+    }
+
+    /// Simulates the action's transaction and predicts its outcome. Errors are
+    /// returned only if the simulation couldn't be run.
+    async fn simulate(&self, action: &SubmitStellarTx) -> Result<PaperTrade> {
+        let tx = unsigned_transaction(action)?;
+        let sim_res = self
+            .rpc
+            .simulate_transaction_envelope(&TransactionEnvelope::Tx(TransactionV1Envelope {
+                tx: tx.clone(),
+                signatures: VecM::default(),
+            }))
+            .await?;
+        let call = action.contract_call();
+        let mut trade = PaperTrade {
+            action_id: action.action_id.clone(),
+            contract_id: call.map(|call| call.contract_address.to_string()),
+            function: call.map(|call| call.function_name.to_utf8_string_lossy()),
+            target_ledger: action.target_ledger,
+            latest_ledger: sim_res.latest_ledger,
+            simulated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            tx_xdr: None,
+            min_resource_fee: sim_res.min_resource_fee,
+            cpu_insns: sim_res.cost.cpu_insns,
+            mem_bytes: sim_res.cost.mem_bytes,
+            fee: None,
+            status: ExecutionStatus::Failed,
+            error: sim_res.error.clone(),
+        };
+        if trade.error.is_some() {
+            return Ok(trade);
+        }
+        let assembled_tx = Assembled::new(&tx, sim_res)?;
+        let fee = match self.fee_policy.fee(
+            self.fee_policy.inclusion_fee(&self.rpc).await,
+            trade.min_resource_fee,
+            max_fee(action),
+            0,
+        ) {
+            Ok(fee) => fee,
+            Err(e) => {
+                trade.error = Some(format!("{:#}", e));
+                return Ok(trade);
+            }
+        };
+        let tx = Transaction {
+            fee,
+            ..assembled_tx.transaction().clone()
+        };
+        trade.tx_xdr = Some(tx.to_xdr_base64(Limits::none())?);
+        trade.fee = Some(fee);
+        trade.status = ExecutionStatus::Success;
+        Ok(trade)
+    }
+}
+
+#[async_trait]
+impl Executor<SubmitStellarTx> for SimulatingExecutor {
+    /// Simulate the transaction and record it instead of sending it.
+    async fn execute(&self, action: SubmitStellarTx) -> Result<()> {
+        let trade = self.simulate(&action).await?;
+        info!(
+            "paper trade {}: {:?} with fee {:?} {}",
+            trade.action_id,
+            trade.status,
+            trade.fee,
+            trade.error.clone().unwrap_or_default()
+        );
+        let mut output = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        writeln!(output, "{}", serde_json::to_string(&trade)?)?;
+        Ok(())
+    }
+}
@@ -1,57 +1,195 @@
+use crate::collectors::account_collector::AccountState;
+use crate::collectors::execution_result_collector::{
+    ExecutionResult, ExecutionResultSender, ExecutionStatus,
+};
+use crate::executors::attempts::{contract_events, AttemptStore, TxAttempt};
 use crate::types::Executor;
+use crate::utilities::{
+    fee_policy::{FeePolicy, MIN_INCLUSION_FEE},
+    notifier::{notify, Notifier, SlackNotifier},
+    rpc::RpcClient,
+    sequence::SequenceManager,
+};
 use anyhow::Result;
 use async_trait::async_trait;
 use ed25519_dalek::ed25519::signature::Signer;
 use ed25519_dalek::SigningKey;
-use reqwest;
-use std::{fs::OpenOptions, io::Write, path::Path, thread::sleep, time::Duration};
-use stellar_rpc_client::Client;
+use jsonrpsee_core::params::ObjectParams;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use stellar_rpc_client::Assembled;
 use stellar_strkey::{ed25519::PublicKey as Ed25519PublicKey, Strkey};
 use stellar_xdr::curr::{
-    DecoratedSignature, Memo, Operation, Preconditions, Signature, SignatureHint, Transaction,
-    TransactionEnvelope, TransactionV1Envelope, Uint256,
+    DecoratedSignature, FeeBumpTransaction, FeeBumpTransactionEnvelope, FeeBumpTransactionExt,
+    FeeBumpTransactionInnerTx, Hash, HostFunction, InvokeContractArgs, Limits, Memo, MuxedAccount,
+    Operation, OperationBody, Preconditions, ReadXdr, Signature, SignatureHint, TimeBounds,
+    TimePoint, Transaction, TransactionEnvelope, TransactionResult, TransactionResultResult,
+    TransactionSignaturePayload, TransactionSignaturePayloadTaggedTransaction,
+    TransactionV1Envelope, Uint256, WriteXdr,
 };
-use tracing::{error, info};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// How long to wait for a submitted transaction to be included in a ledger.
+const INCLUSION_TIMEOUT: Duration = Duration::from_secs(30);
 
-/// An executor that sends transactions to the mempool.
+/// How long a transaction paid through a fee bump stays valid, so a stuck one
+/// can be re-bid before it expires and its sequence number is given up on.
+const FEE_BUMP_VALIDITY: Duration = Duration::from_secs(90);
+
+/// stellar-core only replaces a queued transaction with a fee bump whose
+/// inclusion fee is at least this many times the queued one's.
+const REPLACE_BY_FEE_MULTIPLIER: i128 = 10;
+
+/// How many seconds after a transaction's max time it can't be applied anymore,
+/// as ledgers close a few seconds behind the clock.
+const EXPIRY_MARGIN: u64 = 6;
+
+/// An executor that sends transactions to the mempool. Sequence numbers are
+/// counted locally, so several transactions from one account can be in flight.
+/// It has no [ordering key](Executor::ordering_key): transactions from one
+/// account are sent in sequence order because each holds its sequence number
+/// reservation until it's sent, not because they run one after another.
+/// Fees are paid by the signing account, or by a separate fee account through
+/// fee bumps.
 pub struct SorobanExecutor {
     network_passphrase: String,
-    rpc: Client,
+    rpc: RpcClient,
     log_path: String,
-    slack_api_url_key: String,
+    notifier: Option<Arc<dyn Notifier>>,
+    result_sender: Option<ExecutionResultSender>,
+    fee_reserve: Option<watch::Receiver<Option<AccountState>>>,
+    sequences: SequenceManager,
+    fee_policy: FeePolicy,
+    attempt_store: Option<Arc<dyn AttemptStore>>,
+    fee_account: Option<SigningKey>,
+    low_balance_alert: Option<i128>,
+    /// Whether the low balance alert went out since the balance was last above it.
+    low_balance_alerted: AtomicBool,
+}
+
+/// A signed transaction paid through a fee bump that wasn't included in time.
+/// It's re-bid with a new fee bump instead of being replaced, so it keeps its
+/// sequence number, until it expires.
+struct StuckTx {
+    envelope: TransactionV1Envelope,
+    resource_fee: u64,
+    /// The fee of the latest fee bump sent for the transaction.
+    fee: u32,
+    /// The hash of the latest fee bump sent for the transaction.
+    tx_hash: [u8; 32],
+    /// When the transaction expires, in seconds since the unix epoch.
+    expires_at: u64,
 }
 
 /// Information about the gas bid for a transaction.
 #[derive(Debug, Clone)]
 pub struct GasBidInfo {
-    /// Total profit expected from opportunity in XLM
+    /// Total profit expected from opportunity in stroops
     pub total_profit: i128,
-    /// Percentage of bid profit to use for gas
+    /// Percentage of the profit the total fee is capped at, 0 for no cap
     pub bid_percentage: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct SubmitStellarTx {
+    /// Identifies the action in the [ExecutionResult](crate::collectors::execution_result_collector::ExecutionResult) reported for it.
+    pub action_id: String,
     pub op: Operation,
     pub gas_bid_info: Option<GasBidInfo>,
     pub signing_key: SigningKey,
     pub max_retries: u32,
+    /// The last ledger the transaction is worth submitting in, if it has one.
+    pub target_ledger: Option<u32>,
+}
+
+impl SubmitStellarTx {
+    /// Returns the contract call the transaction makes, if it invokes a contract.
+    pub fn contract_call(&self) -> Option<&InvokeContractArgs> {
+        match &self.op.body {
+            OperationBody::InvokeHostFunction(body) => match &body.host_function {
+                HostFunction::InvokeContract(args) => Some(args),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 impl SorobanExecutor {
     pub async fn new(
-        rpc_url: &str,
+        rpc: RpcClient,
         network_passphrase: &str,
         log_path: &str,
         slack_api_url_key: &str,
     ) -> Self {
         Self {
-            rpc: Client::new(rpc_url).unwrap(),
+            rpc,
             network_passphrase: network_passphrase.to_string(),
             log_path: log_path.to_string(),
-            slack_api_url_key: slack_api_url_key.to_string(),
+            notifier: Some(Arc::new(SlackNotifier::new(slack_api_url_key))),
+            result_sender: None,
+            fee_reserve: None,
+            sequences: SequenceManager::new(),
+            fee_policy: FeePolicy::default(),
+            attempt_store: None,
+            fee_account: None,
+            low_balance_alert: None,
+            low_balance_alerted: AtomicBool::new(false),
         }
     }
+
+    /// Report the result of every executed action through `sender`, usually
+    /// obtained from an [ExecutionResultCollector](crate::collectors::execution_result_collector::ExecutionResultCollector).
+    pub fn with_result_sender(mut self, sender: ExecutionResultSender) -> Self {
+        self.result_sender = Some(sender);
+        self
+    }
+
+    /// Record every attempt at submitting an action in `store`.
+    pub fn with_attempt_store(mut self, store: Arc<dyn AttemptStore>) -> Self {
+        self.attempt_store = Some(store);
+        self
+    }
+
+    /// Bid fees according to `fee_policy` instead of the default one.
+    pub fn with_fee_policy(mut self, fee_policy: FeePolicy) -> Self {
+        self.fee_policy = fee_policy;
+        self
+    }
+
+    /// Refuse to submit transactions whose fee the account paying it can't pay
+    /// without dipping into its minimum balance, given the account's latest state,
+    /// usually obtained from an [AccountCollector](crate::collectors::account_collector::AccountCollector).
+    pub fn with_fee_reserve(mut self, account: watch::Receiver<Option<AccountState>>) -> Self {
+        self.fee_reserve = Some(account);
+        self
+    }
+
+    /// Pay the fees of every transaction from the account of `fee_key`, by
+    /// wrapping it in a fee bump. The signing account only needs to hold what the
+    /// transaction itself spends.
+    pub fn with_fee_account(mut self, fee_key: SigningKey) -> Self {
+        self.fee_account = Some(fee_key);
+        self
+    }
+
+    /// Alert once whenever the spendable balance of the account paying fees, as
+    /// followed through [with_fee_reserve](SorobanExecutor::with_fee_reserve),
+    /// drops below `threshold` stroops.
+    pub fn with_low_balance_alert(mut self, threshold: i128) -> Self {
+        self.low_balance_alert = Some(threshold);
+        self
+    }
 }
 
 #[async_trait]
@@ -59,145 +197,638 @@ impl Executor<SubmitStellarTx> for SorobanExecutor {
     /// Send a transaction to the mempool.
     async fn execute(&self, action: SubmitStellarTx) -> Result<()> {
         let mut retry_counter = 0;
-        while retry_counter <= action.max_retries {
-            let result = submit(
-                &self.rpc,
+        let mut stuck = None;
+        let result = loop {
+            let submitted_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let attempt = match self
+                .submit(&action, retry_counter, submitted_at, &mut stuck)
+                .await
+            {
+                Ok(attempt) => attempt,
+                Err(e) => TxAttempt::unsent(
+                    &action.action_id,
+                    retry_counter,
+                    submitted_at,
+                    format!("{:#}", e),
+                ),
+            };
+            self.record(&attempt);
+            let result = attempt.execution_result();
+            if result.status == ExecutionStatus::Success || retry_counter >= action.max_retries {
+                break result;
+            }
+            retry_counter += 1;
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        };
+        // a stuck tx that won't be re-bid may still land until it expires, which
+        // is reported once it's known without holding up this result
+        if let Some(stuck_tx) = stuck {
+            let rpc = self.rpc.clone();
+            let attempt_store = self.attempt_store.clone();
+            let result_sender = self.result_sender.clone();
+            let action_id = action.action_id.clone();
+            tokio::spawn(async move {
+                let Some(attempt) = settle(&rpc, &action_id, retry_counter, stuck_tx).await else {
+                    return;
+                };
+                record(&attempt_store, &attempt);
+                if attempt.status != ExecutionStatus::NotIncluded {
+                    send_result(&result_sender, attempt.execution_result());
+                }
+            });
+        }
+
+        send_result(&self.result_sender, result.clone());
+        if result.status == ExecutionStatus::Success {
+            return Ok(());
+        }
+
+        error!("Failed to submit tx: {:?}", action.op);
+        let msg = format!(
+            "Failed to submit tx: {:?} {:?} with error: {:#?}",
+            action.op, action.gas_bid_info, result.error
+        );
+        let public_key = Strkey::PublicKeyEd25519(Ed25519PublicKey(
+            action.signing_key.verifying_key().to_bytes(),
+        ))
+        .to_string();
+        let tx = match action.op.body.clone() {
+            stellar_xdr::curr::OperationBody::InvokeHostFunction(body) => Some(body.host_function),
+            _ => None,
+        };
+        notify(
+            &self.notifier,
+            &format!(
+                "<!channel> - Liquidator: {} Failed to submit tx: {:?} Tx Error: {:?}",
+                public_key, tx, result.error
+            ),
+        )
+        .await;
+        let file_path = Path::new(&self.log_path).join("error_logs.txt");
+        let mut output = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(file_path)?;
+        writeln!(output, "{}", msg)?;
+        output.flush().unwrap();
+        Err(anyhow::anyhow!(
+            "tx {} was not applied: {:?}",
+            action.action_id,
+            result.error
+        ))
+    }
+}
+
+impl SorobanExecutor {
+    /// Submits the action as a transaction and waits for it to be included. The
+    /// fee of a time-critical action escalates with every `retry`. With a fee
+    /// account, a transaction that isn't included in time is left in `stuck`,
+    /// and re-bid on the next retry instead of being replaced. Errors are
+    /// returned for failures before the transaction was sent, including a fee
+    /// above the spendable balance of the account paying it, if it's known.
+    async fn submit(
+        &self,
+        action: &SubmitStellarTx,
+        retry: u32,
+        submitted_at: u64,
+        stuck: &mut Option<StuckTx>,
+    ) -> Result<TxAttempt> {
+        if let Some(stuck_tx) = stuck.take() {
+            if submitted_at < stuck_tx.expires_at {
+                return self
+                    .rebid(action, retry, submitted_at, stuck_tx, stuck)
+                    .await;
+            }
+            // an expired tx can't land anymore, so the action is submitted anew unless it did
+            if let Some(attempt) = settle(&self.rpc, &action.action_id, retry, stuck_tx).await {
+                return Ok(attempt);
+            }
+        }
+
+        let rpc = &self.rpc;
+        let account = signer(action);
+        let tx = unsigned_transaction(action)?;
+        let assembled_tx = rpc.simulate_and_assemble_transaction(&tx).await?;
+        let resource_fee = assembled_tx.sim_response().min_resource_fee;
+        let inclusion_fee = self.fee_policy.inclusion_fee(rpc).await;
+        let fee = match &self.fee_account {
+            Some(_) => self.fee_policy.fee_bump_fee(
+                inclusion_fee,
+                resource_fee,
+                max_fee(action),
+                escalations(action, retry),
+            )?,
+            None => self.fee_policy.fee(
+                inclusion_fee,
+                resource_fee,
+                max_fee(action),
+                escalations(action, retry),
+            )?,
+        };
+        self.check_fee_reserve(fee).await?;
+
+        let reservation = self.sequences.reserve(rpc, &account).await?;
+        let expires_at = submitted_at + FEE_BUMP_VALIDITY.as_secs();
+        let tx = match &self.fee_account {
+            // a fee bump pays the fee, so the inner tx only covers its resources,
+            // and expires so a stuck one frees its sequence number
+            Some(_) => Transaction {
+                fee: MIN_INCLUSION_FEE,
+                cond: Preconditions::Time(TimeBounds {
+                    min_time: TimePoint(0),
+                    max_time: TimePoint(expires_at),
+                }),
+                ..tx
+            },
+            None => Transaction { fee, ..tx },
+        };
+        let tx = Transaction {
+            seq_num: stellar_xdr::curr::SequenceNumber(reservation.sequence()),
+            ..tx
+        };
+        let signed = sign(
+            &tx,
+            &assembled_tx,
+            &action.signing_key,
+            &self.network_passphrase,
+        )
+        .and_then(|(tx_hash, inner_envelope)| match &self.fee_account {
+            Some(fee_key) => fee_bump(
+                inner_envelope.clone(),
+                fee,
+                fee_key,
                 &self.network_passphrase,
-                &action,
-                &self.log_path,
-                &self.slack_api_url_key,
             )
-            .await;
-            match result {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(e) => {
-                    retry_counter += 1;
-                    if retry_counter >= action.max_retries {
-                        error!("Failed to submit tx: {:?}", action.op);
-                        let msg = format!(
-                            "Failed to submit tx: {:?} {:?} with error: {:#?}",
-                            action.op, action.gas_bid_info, e
-                        );
-
-                        if !self.slack_api_url_key.is_empty() {
-                            let public_key = Strkey::PublicKeyEd25519(Ed25519PublicKey(
-                                action.signing_key.verifying_key().to_bytes(),
-                            ))
-                            .to_string();
-                            let tx = match action.op.body.clone() {
-                                stellar_xdr::curr::OperationBody::InvokeHostFunction(body) => {
-                                    Some(body.host_function)
-                                }
-                                _ => None,
-                            };
-                            let slack_msg = serde_json::json!({
-                                "text": format!("<!channel> - Liquidator: {} Failed to submit tx: {:?} Tx Error: {:?}", public_key, tx, e)
-                            }).to_string();
-                            reqwest::Client::new()
-                                .post(self.slack_api_url_key.clone())
-                                .body(slack_msg)
-                                .send()
-                                .await?;
-                        }
-                        let file_path = Path::new(&self.log_path).join("error_logs.txt");
-                        let mut output = OpenOptions::new()
-                            .append(true)
-                            .create(true)
-                            .open(file_path)?;
-                        writeln!(output, "{}", msg)?;
-                        output.flush().unwrap();
+            .map(|(tx_hash, envelope)| (tx_hash, envelope, inner_envelope)),
+            None => Ok((
+                tx_hash,
+                TransactionEnvelope::Tx(inner_envelope.clone()),
+                inner_envelope,
+            )),
+        });
+        // an unsent tx leaves a gap in the sequence, so the next one is read from chain
+        let sent = match signed {
+            Ok((tx_hash, signed_tx_envelope, inner_envelope)) => rpc
+                .send_transaction(&signed_tx_envelope)
+                .await
+                .map(|_| (tx_hash, inner_envelope))
+                .map_err(|e| e.into()),
+            Err(e) => Err(e),
+        };
+        let (tx_hash, inner_envelope) = match sent {
+            Ok(sent) => sent,
+            Err(e) => {
+                reservation.resync();
+                return Err(e);
+            }
+        };
+        // the next tx from the account may be sent while this one waits for inclusion
+        drop(reservation);
+
+        let res = self
+            .wait_for_inclusion(action, retry, submitted_at, fee, tx_hash)
+            .await?;
+        // a tx that wasn't included didn't use its sequence number, unless it's re-bid
+        if res.status == ExecutionStatus::NotIncluded && self.fee_account.is_some() {
+            self.sequences.expire(&account, expires_at + EXPIRY_MARGIN);
+            *stuck = Some(StuckTx {
+                envelope: inner_envelope,
+                resource_fee,
+                fee,
+                tx_hash,
+                expires_at,
+            });
+        } else if res.status == ExecutionStatus::NotIncluded
+            || res.error.as_deref() == Some("TxBadSeq")
+        {
+            self.sequences.resync(&account).await;
+        }
+        Ok(res)
+    }
+
+    /// Re-bids a stuck transaction with a fee bump high enough to replace it in
+    /// the queue, and waits for it to be included. A re-bid that wouldn't change
+    /// anything, because the action isn't worth outbidding the network for or
+    /// the fee cap doesn't leave room, waits on the stuck transaction instead.
+    /// The stuck transaction is left in `stuck` unless it was included.
+    async fn rebid(
+        &self,
+        action: &SubmitStellarTx,
+        retry: u32,
+        submitted_at: u64,
+        stuck_tx: StuckTx,
+        stuck: &mut Option<StuckTx>,
+    ) -> Result<TxAttempt> {
+        let fee = match action.target_ledger {
+            Some(_) => self.rebid_fee(action, retry, &stuck_tx).await,
+            None => None,
+        };
+        let (fee, tx_hash) = match (fee, &self.fee_account) {
+            (Some(fee), Some(fee_key)) => {
+                let sent = match self.check_fee_reserve(fee).await {
+                    Ok(()) => match fee_bump(
+                        stuck_tx.envelope.clone(),
+                        fee,
+                        fee_key,
+                        &self.network_passphrase,
+                    ) {
+                        Ok((tx_hash, envelope)) => self
+                            .rpc
+                            .send_transaction(&envelope)
+                            .await
+                            .map(|_| tx_hash)
+                            .map_err(|e| e.into()),
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e),
+                };
+                match sent {
+                    Ok(tx_hash) => (fee, tx_hash),
+                    Err(e) => {
+                        *stuck = Some(stuck_tx);
+                        return Err(e);
                     }
-                    sleep(Duration::from_millis(500));
                 }
             }
+            _ => (stuck_tx.fee, stuck_tx.tx_hash),
+        };
+        let res = self
+            .wait_for_inclusion(action, retry, submitted_at, fee, tx_hash)
+            .await;
+        if !matches!(&res, Ok(res) if res.status != ExecutionStatus::NotIncluded) {
+            *stuck = Some(StuckTx {
+                fee,
+                tx_hash,
+                ..stuck_tx
+            });
+        }
+        res
+    }
+
+    /// Returns the fee to re-bid a stuck transaction with, if the fee cap leaves
+    /// room to replace it in the queue.
+    async fn rebid_fee(
+        &self,
+        action: &SubmitStellarTx,
+        retry: u32,
+        stuck_tx: &StuckTx,
+    ) -> Option<u32> {
+        let resource_fee = stuck_tx.resource_fee as i128;
+        let replacing_fee =
+            resource_fee + (stuck_tx.fee as i128 - resource_fee) * REPLACE_BY_FEE_MULTIPLIER;
+        let fee = self
+            .fee_policy
+            .fee_bump_fee(
+                self.fee_policy.inclusion_fee(&self.rpc).await,
+                stuck_tx.resource_fee,
+                max_fee(action),
+                escalations(action, retry),
+            )
+            .ok()?;
+        let fee = (fee as i128).max(replacing_fee);
+        if max_fee(action).is_some_and(|max_fee| fee > max_fee) {
+            return None;
+        }
+        u32::try_from(fee).ok()
+    }
+
+    /// Waits for the transaction `tx_hash` to be included, and logs the outcome.
+    async fn wait_for_inclusion(
+        &self,
+        action: &SubmitStellarTx,
+        retry: u32,
+        submitted_at: u64,
+        fee: u32,
+        tx_hash: [u8; 32],
+    ) -> Result<TxAttempt> {
+        let sent = TxAttempt {
+            fee_bid: Some(fee),
+            tx_hash: Some(Hash(tx_hash).to_string()),
+            ..TxAttempt::unsent(&action.action_id, retry, submitted_at, String::new())
+        };
+        let res = match poll_transaction(&self.rpc, &Hash(tx_hash), INCLUSION_TIMEOUT).await {
+            Ok(res) => TxAttempt {
+                status: res.status,
+                ledger: res.ledger,
+                fee_charged: res.fee_charged,
+                result_xdr: res.result_xdr,
+                result: res.result,
+                events: res.events,
+                error: res.error,
+                ..sent
+            },
+            Err(e) => TxAttempt {
+                error: Some(format!("{:#}", e)),
+                ..sent
+            },
+        };
+        let log_msg = format!(
+            "Submitted tx {}: {:?} with response: {:?} in ledger {:?}, fee charged {:?}\n",
+            Hash(tx_hash),
+            match action.op.body.clone() {
+                stellar_xdr::curr::OperationBody::InvokeHostFunction(body) =>
+                    Some(body.host_function),
+                _ => None,
+            },
+            res.status,
+            res.ledger,
+            res.fee_charged,
+        );
+        info!("{}", log_msg);
+        log_transaction(&log_msg, &self.log_path)?;
+        notify(
+            &self.notifier,
+            &format!(
+                "<!channel> - Liquidator: {} {}",
+                Strkey::PublicKeyEd25519(Ed25519PublicKey(
+                    action.signing_key.verifying_key().to_bytes(),
+                )),
+                log_msg
+            ),
+        )
+        .await;
+        Ok(res)
+    }
+
+    /// Records the attempt in the attempt store, if there is one.
+    fn record(&self, attempt: &TxAttempt) {
+        record(&self.attempt_store, attempt);
+    }
+
+    /// Returns an error if `fee` exceeds the spendable balance of the account
+    /// paying it, and sends the low balance alert once that balance drops below
+    /// its threshold. The account isn't known until the collector first reads it.
+    async fn check_fee_reserve(&self, fee: u32) -> Result<()> {
+        let spendable = self
+            .fee_reserve
+            .as_ref()
+            .and_then(|account| account.borrow().as_ref().map(|a| a.spendable_balance()));
+        let spendable = match spendable {
+            Some(spendable) => spendable,
+            None => return Ok(()),
+        };
+        if let Some(threshold) = self.low_balance_alert {
+            let low = spendable < threshold;
+            if low && !self.low_balance_alerted.swap(true, Ordering::Relaxed) {
+                let fee_payer = match &self.fee_account {
+                    Some(fee_key) => Strkey::PublicKeyEd25519(Ed25519PublicKey(
+                        fee_key.verifying_key().to_bytes(),
+                    ))
+                    .to_string(),
+                    None => "signing account".to_string(),
+                };
+                let msg = format!(
+                    "Fee account {} is low with {} stroops spendable",
+                    fee_payer, spendable
+                );
+                warn!("{}", msg);
+                notify(&self.notifier, &format!("<!channel> - Liquidator: {}", msg)).await;
+            } else if !low {
+                self.low_balance_alerted.store(false, Ordering::Relaxed);
+            }
+        }
+        if fee as i128 > spendable {
+            return Err(anyhow::anyhow!(
+                "fee of {} stroops exceeds the spendable balance of {} stroops",
+                fee,
+                spendable
+            ));
         }
         Ok(())
     }
 }
 
-async fn submit(
-    rpc: &Client,
-    network_passphrase: &str,
-    action: &SubmitStellarTx,
-    log_path: &str,
-    slack_api_url_key: &str,
-) -> Result<()> {
-    let mut seq_num = rpc
-        .get_account(
-            &Strkey::PublicKeyEd25519(Ed25519PublicKey(
-                action.signing_key.verifying_key().to_bytes(),
-            ))
-            .to_string(),
-        )
-        .await?
-        .seq_num
-        .into();
-    seq_num += 1;
-    let fee = match action.gas_bid_info {
-        Some(ref gas_bid_info) => {
-            (gas_bid_info.total_profit * gas_bid_info.bid_percentage as i128 / 100) as u32
+/// Records the attempt in `store`, if there is one.
+fn record(store: &Option<Arc<dyn AttemptStore>>, attempt: &TxAttempt) {
+    if let Some(store) = store {
+        if let Err(e) = store.record(attempt) {
+            error!(
+                "Error recording attempt {} of {}: {}",
+                attempt.attempt, attempt.action_id, e
+            );
         }
-        None => 10000,
-    };
-    let tx = Transaction {
+    }
+}
+
+/// Reports an execution result through `sender`, if there is one.
+fn send_result(sender: &Option<ExecutionResultSender>, result: ExecutionResult) {
+    if let Some(sender) = sender {
+        if sender.send(result.clone()).is_err() {
+            warn!("No receivers for execution result of {}", result.action_id);
+        }
+    }
+}
+
+/// Waits for a stuck transaction to expire, and returns its outcome if it was
+/// included after all. The sequence number it held is read from chain again
+/// through [SequenceManager::expire].
+async fn settle(
+    rpc: &RpcClient,
+    action_id: &str,
+    retry: u32,
+    stuck_tx: StuckTx,
+) -> Option<TxAttempt> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let expiry = (stuck_tx.expires_at + EXPIRY_MARGIN).saturating_sub(now);
+    tokio::time::sleep(Duration::from_secs(expiry)).await;
+    let res = poll_transaction(rpc, &Hash(stuck_tx.tx_hash), Duration::ZERO)
+        .await
+        .ok()?;
+    Some(TxAttempt {
+        action_id: action_id.to_string(),
+        attempt: retry,
+        submitted_at: now,
+        tx_hash: Some(Hash(stuck_tx.tx_hash).to_string()),
+        fee_bid: Some(stuck_tx.fee),
+        ..res
+    })
+}
+
+/// Returns how many times the fee of the action's transaction escalates on
+/// `retry`. Only fills that must land by a target ledger are worth outbidding
+/// the network for.
+fn escalations(action: &SubmitStellarTx, retry: u32) -> u32 {
+    match action.target_ledger {
+        Some(_) => retry,
+        None => 0,
+    }
+}
+
+/// Returns the account that signs the action's transaction.
+fn signer(action: &SubmitStellarTx) -> String {
+    Strkey::PublicKeyEd25519(Ed25519PublicKey(
+        action.signing_key.verifying_key().to_bytes(),
+    ))
+    .to_string()
+}
+
+/// Builds the transaction for the action, before simulation. Simulation ignores
+/// the fee and sequence number, so they're left to be set once the tx is ready
+/// to send.
+pub(crate) fn unsigned_transaction(action: &SubmitStellarTx) -> Result<Transaction> {
+    Ok(Transaction {
         source_account: stellar_xdr::curr::MuxedAccount::Ed25519(Uint256(
             action.signing_key.verifying_key().to_bytes(),
         )),
-        fee,
-        seq_num: stellar_xdr::curr::SequenceNumber(seq_num),
+        fee: MIN_INCLUSION_FEE,
+        seq_num: stellar_xdr::curr::SequenceNumber(0),
         cond: Preconditions::None,
         memo: Memo::None,
         operations: vec![action.op.clone()].try_into()?,
         ext: stellar_xdr::curr::TransactionExt::V0,
+    })
+}
+
+/// Returns the most the action's transaction may pay in fees, if it's capped.
+pub(crate) fn max_fee(action: &SubmitStellarTx) -> Option<i128> {
+    action
+        .gas_bid_info
+        .as_ref()
+        .filter(|gas_bid_info| gas_bid_info.bid_percentage > 0)
+        .map(|gas_bid_info| {
+            gas_bid_info
+                .total_profit
+                .saturating_mul(gas_bid_info.bid_percentage as i128)
+                / 100
+        })
+}
+
+/// Assembles `tx` with the simulation results of `assembled_tx` and signs it.
+/// Returns the hash of the assembled transaction and its signed envelope.
+fn sign(
+    tx: &Transaction,
+    assembled_tx: &Assembled,
+    signing_key: &SigningKey,
+    network_passphrase: &str,
+) -> Result<([u8; 32], TransactionV1Envelope)> {
+    let assembled_tx = Assembled::new(tx, assembled_tx.sim_response().clone())?;
+    let tx_hash = hash(assembled_tx.transaction(), network_passphrase)?;
+    let signed_tx_envelope = TransactionV1Envelope {
+        tx: assembled_tx.transaction().clone(),
+        signatures: [decorated_signature(&tx_hash, signing_key)?].try_into()?,
     };
-    let assembled_tx = rpc.simulate_and_assemble_transaction(&tx.clone()).await?;
-    let tx_hash = assembled_tx.hash(network_passphrase);
-    if tx_hash.is_err() {
-        return Err(anyhow::anyhow!("Failed to hash tx"));
-    }
-    let tx_signature = action.signing_key.sign(&tx_hash.unwrap());
-    let decorated_signature = DecoratedSignature {
-        hint: SignatureHint(action.signing_key.verifying_key().to_bytes()[28..].try_into()?),
-        signature: Signature(tx_signature.to_bytes().try_into()?),
+    Ok((tx_hash, signed_tx_envelope))
+}
+
+/// Wraps the signed `inner` transaction in a fee bump bidding `fee`, paid and
+/// signed by the account of `fee_key`. Returns the hash of the fee bump and its
+/// signed envelope.
+fn fee_bump(
+    inner: TransactionV1Envelope,
+    fee: u32,
+    fee_key: &SigningKey,
+    network_passphrase: &str,
+) -> Result<([u8; 32], TransactionEnvelope)> {
+    let tx = FeeBumpTransaction {
+        fee_source: MuxedAccount::Ed25519(Uint256(fee_key.verifying_key().to_bytes())),
+        fee: fee as i64,
+        inner_tx: FeeBumpTransactionInnerTx::Tx(inner),
+        ext: FeeBumpTransactionExt::V0,
     };
-    let signed_tx_envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
-        tx: assembled_tx.transaction().clone(),
-        signatures: [decorated_signature].try_into()?,
+    let tx_hash = signature_payload_hash(
+        TransactionSignaturePayloadTaggedTransaction::TxFeeBump(tx.clone()),
+        network_passphrase,
+    )?;
+    let signed_tx_envelope = TransactionEnvelope::TxFeeBump(FeeBumpTransactionEnvelope {
+        tx,
+        signatures: [decorated_signature(&tx_hash, fee_key)?].try_into()?,
     });
+    Ok((tx_hash, signed_tx_envelope))
+}
 
-    let res = rpc.send_transaction_polling(&signed_tx_envelope).await?;
+/// Returns the hash `tx` is signed and looked up by.
+fn hash(tx: &Transaction, network_passphrase: &str) -> Result<[u8; 32]> {
+    signature_payload_hash(
+        TransactionSignaturePayloadTaggedTransaction::Tx(tx.clone()),
+        network_passphrase,
+    )
+}
 
-    let log_msg = format!(
-        "Submitted tx: {:?} with response: {:?}\n",
-        match action.op.body.clone() {
-            stellar_xdr::curr::OperationBody::InvokeHostFunction(body) => Some(body.host_function),
+fn signature_payload_hash(
+    tagged_transaction: TransactionSignaturePayloadTaggedTransaction,
+    network_passphrase: &str,
+) -> Result<[u8; 32]> {
+    let payload = TransactionSignaturePayload {
+        network_id: Hash(Sha256::digest(network_passphrase).into()),
+        tagged_transaction,
+    };
+    Ok(Sha256::digest(payload.to_xdr(Limits::none())?).into())
+}
+
+fn decorated_signature(tx_hash: &[u8; 32], signing_key: &SigningKey) -> Result<DecoratedSignature> {
+    Ok(DecoratedSignature {
+        hint: SignatureHint(signing_key.verifying_key().to_bytes()[28..].try_into()?),
+        signature: Signature(signing_key.sign(tx_hash).to_bytes().try_into()?),
+    })
+}
+
+/// Polls `getTransaction` until the transaction is included in a ledger or
+/// `timeout` passes. Only the outcome of the returned attempt is filled in.
+async fn poll_transaction(rpc: &RpcClient, tx_hash: &Hash, timeout: Duration) -> Result<TxAttempt> {
+    let start = tokio::time::Instant::now();
+    loop {
+        let mut params = ObjectParams::new();
+        params.insert("hash", tx_hash.to_string())?;
+        let res: serde_json::Value = rpc.request("getTransaction", params).await?;
+        let status = match res["status"].as_str() {
+            Some("SUCCESS") => ExecutionStatus::Success,
+            Some("FAILED") => ExecutionStatus::Failed,
+            Some("NOT_FOUND") => {
+                if start.elapsed() >= timeout {
+                    return Err(anyhow::anyhow!(
+                        "Tx not included after {}s",
+                        timeout.as_secs()
+                    ));
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            status => return Err(anyhow::anyhow!("Unexpected tx status: {:?}", status)),
+        };
+        let result_xdr = res["resultXdr"].as_str().map(|xdr| xdr.to_string());
+        let tx_result = match &result_xdr {
+            Some(xdr) => Some(TransactionResult::from_xdr_base64(xdr, Limits::none())?),
+            None => None,
+        };
+        let error = match (&status, &tx_result) {
+            (ExecutionStatus::Failed, Some(tx_result)) => Some(result_name(tx_result)),
+            (ExecutionStatus::Failed, None) => Some("Tx failed".to_string()),
             _ => None,
-        },
-        res.status,
-    );
-    info!("{}", log_msg);
-    log_transaction(&log_msg, log_path)?;
-    if !slack_api_url_key.is_empty() {
-        let slack_msg = serde_json::json!({
-            "text": format!("<!channel> - Liquidator: {} {}",
-            Strkey::PublicKeyEd25519(Ed25519PublicKey(
-                action.signing_key.verifying_key().to_bytes(),
-            ))
-            .to_string(),
-            log_msg
-            )
-        })
-        .to_string();
+        };
+        let events = match res["resultMetaXdr"].as_str() {
+            Some(meta) => contract_events(meta)?,
+            None => vec![],
+        };
+        return Ok(TxAttempt {
+            status,
+            ledger: res["ledger"].as_u64().map(|ledger| ledger as u32),
+            fee_charged: tx_result.as_ref().map(|tx_result| tx_result.fee_charged),
+            result_xdr,
+            result: tx_result.as_ref().map(result_name),
+            events,
+            error,
+            ..TxAttempt::unsent("", 0, 0, String::new())
+        });
+    }
+}
 
-        reqwest::Client::new()
-            .post(slack_api_url_key)
-            .body(slack_msg)
-            .send()
-            .await?;
+/// Returns the name of the transaction's result code, or of the inner
+/// transaction's for a fee bump, e.g. `TxBadSeq` rather than
+/// `TxFeeBumpInnerFailed`.
+fn result_name(tx_result: &TransactionResult) -> String {
+    match &tx_result.result {
+        TransactionResultResult::TxFeeBumpInnerSuccess(inner)
+        | TransactionResultResult::TxFeeBumpInnerFailed(inner) => {
+            inner.result.result.name().to_string()
+        }
+        result => result.name().to_string(),
     }
-    Ok(())
 }
 
 pub fn log_transaction(msg: &str, log_path: &str) -> Result<()> {
@@ -11,27 +11,41 @@
 //! library is made up of three main components:
 //!
 //! 1. [Collectors](types::Collector): *Collectors* take in external events (such as pending txs,
-//!  new blocks, marketplace orders, etc. ) and turn them into an internal
-//! *event* representation.
+//!    new blocks, marketplace orders, etc. ) and turn them into an internal
+//!    *event* representation.
 //!
 //! 2. [Strategies](types::Strategy): *Strategies* contain the core logic required for each MEV
-//! opportunity. They take in *events* as inputs, and compute whether any
-//! opportunities are available (for example, a strategy might listen to a stream
-//! of marketplace orders to see if there are any cross-exchange arbs). *Strategies*
-//! produce *actions*.
+//!    opportunity. They take in *events* as inputs, and compute whether any
+//!    opportunities are available (for example, a strategy might listen to a stream
+//!    of marketplace orders to see if there are any cross-exchange arbs). *Strategies*
+//!    produce *actions*.
 //!
 //! 3. [Executors](types::Executor): *Executors* process *actions*, and are responsible for executing
-//! them in different domains (for example, submitting txs, posting off-chain orders, etc.).
+//!    them in different domains (for example, submitting txs, posting off-chain orders, etc.).
 //!
 //! These components are tied together by the [Engine](engine::Engine), which is responsible for
 //! orchestrating the flow of data between them.
 
 /// This module contains [collector](types::Collector) implementations.
 pub mod collectors;
+/// This module contains the channels the [Engine](engine::Engine) uses to deliver
+/// events and actions to components.
+pub mod delivery;
 /// This module contains the [Engine](engine::Engine) struct, which is responsible
 /// for orchestrating data flows between components
 pub mod engine;
 /// This module contains [executor](types::Executor) implementations.
 pub mod executors;
+/// This module contains the [recorders](recorder::Recorder) the [Engine](engine::Engine)
+/// uses to persist the events it sees.
+pub mod recorder;
+/// This module contains the status the [Engine](engine::Engine) reports for each
+/// of its components.
+pub mod status;
+/// This module contains the restart policies the [Engine](engine::Engine) uses to
+/// supervise collectors and executors.
+pub mod supervisor;
 /// This module contains the core type definitions for Artemis.
 pub mod types;
+/// This module contains helpers shared by the engine and its components.
+pub mod utilities;
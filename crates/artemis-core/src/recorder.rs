@@ -0,0 +1,84 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// An event as stored in a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent<E> {
+    /// When the engine saw the event, in milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub event: E,
+}
+
+/// Recorder trait, which persists the events seen by the [Engine](crate::engine::Engine).
+pub trait Recorder<E>: Send {
+    /// Record an event.
+    fn record(&mut self, event: &E) -> Result<()>;
+}
+
+/// A [Recorder](Recorder) that appends events to a file, one JSON encoded
+/// [RecordedEvent](RecordedEvent) per line.
+pub struct FileRecorder {
+    file: File,
+}
+
+impl FileRecorder {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl<E: Serialize> Recorder<E> for FileRecorder {
+    fn record(&mut self, event: &E) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let line = serde_json::to_string(&RecordedEvent { timestamp, event })?;
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads every event from a recording written by a [FileRecorder](FileRecorder).
+pub fn read_recording<E: DeserializeOwned>(
+    path: impl AsRef<Path>,
+) -> Result<Vec<RecordedEvent<E>>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        events.push(serde_json::from_str(&line)?);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_recording, FileRecorder, Recorder};
+
+    #[test]
+    fn test_recording_round_trip() {
+        let path = std::env::temp_dir().join(format!("recording-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut recorder = FileRecorder::new(&path).unwrap();
+        recorder.record(&"first".to_string()).unwrap();
+        recorder.record(&"second".to_string()).unwrap();
+        // reopening appends to the existing recording
+        let mut recorder = FileRecorder::new(&path).unwrap();
+        recorder.record(&"third".to_string()).unwrap();
+
+        let events = read_recording::<String>(&path).unwrap();
+        let events: Vec<_> = events.into_iter().map(|e| e.event).collect();
+        assert_eq!(events, vec!["first", "second", "third"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
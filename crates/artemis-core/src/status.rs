@@ -0,0 +1,196 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+/// The kind of component a [ComponentStatus](ComponentStatus) describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentKind {
+    Collector,
+    Strategy,
+    Executor,
+}
+
+/// What a component is currently doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    Running,
+    /// A strategy that failed to sync its state and is waiting to resync.
+    Degraded,
+    /// The component failed and is waiting out its restart delay.
+    Restarting,
+    /// An executor whose action panicked, taking no new actions until its delay
+    /// passes.
+    Paused,
+    /// The component finished, was shut down, or exhausted its restart budget.
+    Stopped,
+}
+
+/// A snapshot of a single component of a running [Engine](crate::engine::Engine).
+/// Timestamps are milliseconds since the unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentStatus {
+    pub kind: ComponentKind,
+    /// The position of the component among those of its kind, in the order they
+    /// were added to the engine.
+    pub index: usize,
+    pub state: ComponentState,
+    /// The events a collector emitted, the events a strategy received, or the
+    /// actions an executor received.
+    pub events: u64,
+    pub last_event_at: Option<u64>,
+    /// The actions a strategy emitted, or the actions an executor finished.
+    pub actions: u64,
+    pub last_action_at: Option<u64>,
+    /// Items waiting in the component's channel.
+    pub queue_depth: usize,
+    /// Failures, failed actions, syncs and lag the component ran into.
+    pub errors: u64,
+    pub last_error: Option<String>,
+    pub restarts: u64,
+}
+
+/// A snapshot of every component of a running [Engine](crate::engine::Engine).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EngineStatus {
+    pub started_at: u64,
+    pub components: Vec<ComponentStatus>,
+}
+
+/// The live status of one component, updated by the task running it.
+#[derive(Debug, Clone)]
+pub(crate) struct StatusCell {
+    status: Arc<Mutex<ComponentStatus>>,
+    queued: Option<Arc<AtomicUsize>>,
+}
+
+impl StatusCell {
+    pub(crate) fn new(kind: ComponentKind, index: usize, queued: Option<Arc<AtomicUsize>>) -> Self {
+        let status = ComponentStatus {
+            kind,
+            index,
+            state: ComponentState::Running,
+            events: 0,
+            last_event_at: None,
+            actions: 0,
+            last_action_at: None,
+            queue_depth: 0,
+            errors: 0,
+            last_error: None,
+            restarts: 0,
+        };
+        Self {
+            status: Arc::new(Mutex::new(status)),
+            queued,
+        }
+    }
+
+    pub(crate) fn event(&self) {
+        self.update(|status| {
+            status.events += 1;
+            status.last_event_at = Some(now());
+        });
+    }
+
+    pub(crate) fn action(&self) {
+        self.update(|status| {
+            status.actions += 1;
+            status.last_action_at = Some(now());
+        });
+    }
+
+    pub(crate) fn error(&self, e: &dyn Display) {
+        self.update(|status| {
+            status.errors += 1;
+            status.last_error = Some(e.to_string());
+        });
+    }
+
+    pub(crate) fn restart(&self) {
+        self.update(|status| {
+            status.restarts += 1;
+            status.state = ComponentState::Running;
+        });
+    }
+
+    pub(crate) fn set_state(&self, state: ComponentState) {
+        self.update(|status| status.state = state);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ComponentStatus)) {
+        f(&mut self.status.lock().unwrap());
+    }
+
+    fn snapshot(&self) -> ComponentStatus {
+        let mut status = self.status.lock().unwrap().clone();
+        if let Some(queued) = &self.queued {
+            status.queue_depth = queued.load(Ordering::Relaxed);
+        }
+        status
+    }
+}
+
+/// A cheap, cloneable view of a running engine's components, returned by
+/// [EngineHandle::status](crate::engine::EngineHandle::status).
+#[derive(Debug, Clone)]
+pub struct StatusHandle {
+    started_at: u64,
+    components: Vec<StatusCell>,
+}
+
+impl StatusHandle {
+    pub(crate) fn new(components: Vec<StatusCell>) -> Self {
+        Self {
+            started_at: now(),
+            components,
+        }
+    }
+
+    /// Returns the current status of every component.
+    pub fn snapshot(&self) -> EngineStatus {
+        EngineStatus {
+            started_at: self.started_at,
+            components: self.components.iter().map(StatusCell::snapshot).collect(),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ComponentKind, ComponentState, StatusCell, StatusHandle};
+    use std::sync::{atomic::AtomicUsize, Arc};
+
+    #[test]
+    fn test_snapshot_reflects_updates() {
+        let queued = Arc::new(AtomicUsize::new(3));
+        let cell = StatusCell::new(ComponentKind::Strategy, 1, Some(queued));
+        let handle = StatusHandle::new(vec![cell.clone()]);
+        cell.event();
+        cell.action();
+        cell.error(&"sync failed");
+        cell.set_state(ComponentState::Degraded);
+
+        let status = handle.snapshot().components.remove(0);
+        assert_eq!(status.kind, ComponentKind::Strategy);
+        assert_eq!(status.index, 1);
+        assert_eq!(status.state, ComponentState::Degraded);
+        assert_eq!((status.events, status.actions, status.errors), (1, 1, 1));
+        assert!(status.last_event_at.is_some());
+        assert_eq!(status.last_error.as_deref(), Some("sync failed"));
+        assert_eq!(status.queue_depth, 3);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{collections::VecDeque, fmt::Display, sync::Arc, time::Duration};
+
+use tokio::time::Instant;
+use tracing::{error, warn};
+
+use crate::status::{ComponentState, StatusCell};
+use crate::utilities::{
+    backoff::Backoff,
+    notifier::{notify, Notifier},
+};
+
+/// How the [Engine](crate::engine::Engine) restarts collectors that fail, and
+/// pauses executors whose actions panic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// The most restarts or pauses allowed within `window`. Once exceeded the
+    /// component is stopped and the operator is notified.
+    pub max_restarts: u32,
+    /// The window restarts are counted over.
+    pub window: Duration,
+    /// The delay before each restart or the length of each pause, growing with
+    /// those in the window.
+    pub backoff: Backoff,
+}
+
+impl RestartPolicy {
+    pub fn new(max_restarts: u32, window: Duration, backoff: Backoff) -> Self {
+        Self {
+            max_restarts,
+            window,
+            backoff,
+        }
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(600), Backoff::default())
+    }
+}
+
+/// Tracks the restarts of a single component against its [RestartPolicy](RestartPolicy).
+#[derive(Debug, Clone)]
+pub(crate) struct RestartBudget {
+    policy: RestartPolicy,
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartBudget {
+    pub(crate) fn new(policy: RestartPolicy) -> Self {
+        Self {
+            policy,
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Records a failure at `now` and returns how long to wait before restarting,
+    /// or `None` if the budget is spent.
+    pub(crate) fn next_restart(&mut self, now: Instant) -> Option<Duration> {
+        while let Some(restart) = self.restarts.front() {
+            if now.duration_since(*restart) < self.policy.window {
+                break;
+            }
+            self.restarts.pop_front();
+        }
+        if self.restarts.len() as u32 >= self.policy.max_restarts {
+            return None;
+        }
+        let delay = self.policy.backoff.delay(self.restarts.len() as u32);
+        self.restarts.push_back(now);
+        Some(delay)
+    }
+}
+
+/// Decides whether a failed component is restarted, or paused if it can't be
+/// rebuilt, keeping its status up to date and notifying the operator when it
+/// gives up.
+pub(crate) struct Supervisor {
+    name: String,
+    budget: RestartBudget,
+    status: StatusCell,
+    notifier: Option<Arc<dyn Notifier>>,
+    /// Whether the component pauses instead of restarting.
+    pauses: bool,
+}
+
+impl Supervisor {
+    pub(crate) fn new(
+        name: String,
+        policy: RestartPolicy,
+        status: StatusCell,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) -> Self {
+        Self {
+            name,
+            budget: RestartBudget::new(policy),
+            status,
+            notifier,
+            pauses: false,
+        }
+    }
+
+    /// Pause the component after a failure instead of restarting it.
+    pub(crate) fn pausing(mut self) -> Self {
+        self.pauses = true;
+        self
+    }
+
+    /// Records a failure and returns how long to wait before restarting or
+    /// resuming, or `None` if the component should stop.
+    pub(crate) async fn on_failure(&mut self, e: &(dyn Display + Sync)) -> Option<Duration> {
+        self.status.error(e);
+        match self.budget.next_restart(Instant::now()) {
+            Some(delay) if self.pauses => {
+                warn!("{} failed: {}, pausing it for {:?}", self.name, e, delay);
+                self.status.set_state(ComponentState::Paused);
+                Some(delay)
+            }
+            Some(delay) => {
+                warn!("{} failed: {}, restarting in {:?}", self.name, e, delay);
+                self.status.set_state(ComponentState::Restarting);
+                Some(delay)
+            }
+            None => {
+                let msg = format!(
+                    "{} failed: {} and exhausted its restart budget, stopping it",
+                    self.name, e
+                );
+                error!("{}", msg);
+                notify(&self.notifier, &msg).await;
+                None
+            }
+        }
+    }
+
+    /// Records that the component restarted after its delay.
+    pub(crate) fn restarted(&self) {
+        self.status.restart();
+    }
+
+    /// Records that the component resumed after its pause.
+    pub(crate) fn resumed(&self) {
+        self.status.set_state(ComponentState::Running);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RestartBudget, RestartPolicy};
+    use crate::utilities::backoff::Backoff;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn test_budget_resets_after_window() {
+        let backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        let mut budget =
+            RestartBudget::new(RestartPolicy::new(2, Duration::from_secs(60), backoff));
+        let start = Instant::now();
+        assert_eq!(budget.next_restart(start), Some(Duration::from_secs(1)));
+        assert_eq!(
+            budget.next_restart(start + Duration::from_secs(10)),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(budget.next_restart(start + Duration::from_secs(20)), None);
+        // the first restart has left the window
+        assert_eq!(
+            budget.next_restart(start + Duration::from_secs(61)),
+            Some(Duration::from_secs(2))
+        );
+    }
+}
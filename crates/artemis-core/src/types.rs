@@ -1,3 +1,4 @@
+use crate::delivery::Interest;
 use anyhow::Result;
 use async_trait::async_trait;
 use std::pin::Pin;
@@ -7,14 +8,16 @@ use tokio_stream::StreamExt;
 // use crate::collectors::block_collector::NewBlock;
 // use crate::executors::soroban_executor::SubmitStellarTx;
 
-/// A stream of events emitted by a [Collector](Collector).
-pub type CollectorStream<'a, E> = Pin<Box<dyn Stream<Item = E> + Send + 'a>>;
+/// A stream of events emitted by a [Collector](Collector). An error means the
+/// collector failed and should be restarted, while the end of the stream means
+/// it has no more events to emit.
+pub type CollectorStream<'a, E> = Pin<Box<dyn Stream<Item = Result<E>> + Send + 'a>>;
 
 /// Collector trait, which defines a source of events.
 #[async_trait]
 pub trait Collector<E>: Send + Sync {
     /// Returns the core event stream for the collector.
-    async fn get_event_stream(&mut self) -> Result<CollectorStream<'_, E>>;
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, E>>;
 }
 
 /// Strategy trait, which defines the core logic for each opportunity.
@@ -26,6 +29,25 @@ pub trait Strategy<E, A>: Send + Sync {
 
     /// Process an event, and return an action if needed.
     async fn process_event(&mut self, event: E) -> Vec<A>;
+
+    /// Declare which events the strategy wants. The engine only delivers events
+    /// the interest matches, and asks for it once when it starts.
+    fn interest(&self) -> Interest<E> {
+        Interest::all()
+    }
+
+    /// Called when the strategy fell behind its event stream and `skipped` events
+    /// were dropped. Strategies that cannot tolerate gaps should resync here,
+    /// usually by calling [sync_state](Strategy::sync_state).
+    async fn on_lag(&mut self, _skipped: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once after the last event has been processed during shutdown, so
+    /// the strategy can persist any state it wants to keep.
+    async fn on_shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// Executor trait, responsible for executing actions returned by strategies.
@@ -33,6 +55,13 @@ pub trait Strategy<E, A>: Send + Sync {
 pub trait Executor<A>: Send + Sync {
     /// Execute an action.
     async fn execute(&self, action: A) -> Result<()>;
+
+    /// Returns a key for actions that must execute in the order they arrive,
+    /// such as the account that signs them. Actions without a key may run
+    /// concurrently with anything else.
+    fn ordering_key(&self, _action: &A) -> Option<String> {
+        None
+    }
 }
 
 /// CollectorMap is a wrapper around a [Collector](Collector) that maps outgoing
@@ -54,10 +83,10 @@ where
     E2: Send + Sync + 'static,
     F: Fn(E1) -> E2 + Send + Sync + Clone + 'static,
 {
-    async fn get_event_stream(&mut self) -> Result<CollectorStream<'_, E2>> {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, E2>> {
         let stream = self.collector.get_event_stream().await?;
         let f = self.f.clone();
-        let stream = stream.map(f);
+        let stream = stream.map(move |event| event.map(&f));
         Ok(Box::pin(stream))
     }
 }
@@ -78,7 +107,7 @@ impl<A, F> ExecutorMap<A, F> {
 #[async_trait]
 impl<A1, A2, F> Executor<A1> for ExecutorMap<A2, F>
 where
-    A1: Send + Sync + 'static,
+    A1: Send + Sync + Clone + 'static,
     A2: Send + Sync + 'static,
     F: Fn(A1) -> Option<A2> + Send + Sync + Clone + 'static,
 {
@@ -89,6 +118,10 @@ where
             None => Ok(()),
         }
     }
+
+    fn ordering_key(&self, action: &A1) -> Option<String> {
+        (self.f)(action.clone()).and_then(|action| self.executor.ordering_key(&action))
+    }
 }
 
 pub struct SlackRequestBody {
@@ -0,0 +1,187 @@
+use std::sync::{Arc, Mutex};
+
+use crate::utilities::rpc::RpcClient;
+use anyhow::{anyhow, Result};
+use jsonrpsee_core::params::ObjectParams;
+use serde_json::Value;
+use tracing::warn;
+
+/// The smallest inclusion fee the network accepts for a single operation.
+pub const MIN_INCLUSION_FEE: u32 = 100;
+
+/// The percentiles `getFeeStats` reports, besides the max.
+const FEE_PERCENTILES: [u32; 11] = [10, 20, 30, 40, 50, 60, 70, 80, 90, 95, 99];
+
+/// Picks the fee of a soroban transaction: the resource fee its simulation asks
+/// for, plus an inclusion fee bid at a percentile of the inclusion fees recently
+/// paid on the network. The total can be capped, and the inclusion fee raised on
+/// every retry of a time-critical transaction.
+#[derive(Debug, Clone)]
+pub struct FeePolicy {
+    percentile: u32,
+    default_inclusion_fee: u32,
+    escalation_pct: u32,
+    /// The last inclusion fee read from the fee stats, bid while they can't be read.
+    last_inclusion_fee: Arc<Mutex<Option<u32>>>,
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        Self {
+            percentile: 90,
+            default_inclusion_fee: 10_000,
+            escalation_pct: 50,
+            last_inclusion_fee: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl FeePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the percentile of recent inclusion fees to bid. Percentiles the
+    /// network doesn't report are rounded up to the next one it does.
+    pub fn with_percentile(mut self, percentile: u32) -> Self {
+        self.percentile = percentile;
+        self
+    }
+
+    /// Sets the inclusion fee to bid before fee stats could be read.
+    pub fn with_default_inclusion_fee(mut self, default_inclusion_fee: u32) -> Self {
+        self.default_inclusion_fee = default_inclusion_fee;
+        self
+    }
+
+    /// Sets by how many percent the inclusion fee of a time-critical transaction
+    /// rises on each retry.
+    pub fn with_escalation_pct(mut self, escalation_pct: u32) -> Self {
+        self.escalation_pct = escalation_pct;
+        self
+    }
+
+    /// Returns the inclusion fee to bid, read from `getFeeStats`. If the stats
+    /// can't be read, the last fee read is bid instead, or the default one.
+    pub async fn inclusion_fee(&self, rpc: &RpcClient) -> u32 {
+        let res: Result<Value> = rpc
+            .request("getFeeStats", ObjectParams::new())
+            .await
+            .map_err(|e| e.into());
+        let fee = res.and_then(|stats| {
+            percentile_fee(&stats, self.percentile)
+                .ok_or_else(|| anyhow!("getFeeStats response has no soroban inclusion fees"))
+        });
+        let mut last = self.last_inclusion_fee.lock().unwrap();
+        match fee {
+            Ok(fee) => {
+                *last = Some(fee);
+                fee
+            }
+            Err(e) => {
+                warn!(
+                    "unable to read fee stats, using the last known fee: {:#}",
+                    e
+                );
+                last.unwrap_or(self.default_inclusion_fee)
+            }
+        }
+    }
+
+    /// Returns the total fee for a transaction whose simulation asks for
+    /// `resource_fee`, bidding `inclusion_fee` raised for `escalations` retries.
+    /// The total is capped at `max_fee`, and an error is returned if the cap
+    /// doesn't leave room for the smallest inclusion fee.
+    pub fn fee(
+        &self,
+        inclusion_fee: u32,
+        resource_fee: u64,
+        max_fee: Option<i128>,
+        escalations: u32,
+    ) -> Result<u32> {
+        self.total_fee(inclusion_fee, resource_fee, max_fee, escalations, 1)
+    }
+
+    /// Returns the total fee for a fee bump wrapping a transaction whose
+    /// simulation asks for `resource_fee`, like [fee](FeePolicy::fee) does. A fee
+    /// bump bids the inclusion fee twice, for the inner operation and for itself.
+    pub fn fee_bump_fee(
+        &self,
+        inclusion_fee: u32,
+        resource_fee: u64,
+        max_fee: Option<i128>,
+        escalations: u32,
+    ) -> Result<u32> {
+        self.total_fee(inclusion_fee, resource_fee, max_fee, escalations, 2)
+    }
+
+    fn total_fee(
+        &self,
+        inclusion_fee: u32,
+        resource_fee: u64,
+        max_fee: Option<i128>,
+        escalations: u32,
+        operations: u32,
+    ) -> Result<u32> {
+        let mut inclusion_fee = inclusion_fee.max(MIN_INCLUSION_FEE) as i128;
+        for _ in 0..escalations {
+            inclusion_fee =
+                (inclusion_fee * (100 + self.escalation_pct as i128) / 100).min(u32::MAX as i128);
+        }
+        let resource_fee = resource_fee as i128;
+        let mut fee = resource_fee + inclusion_fee * operations as i128;
+        if let Some(max_fee) = max_fee {
+            if max_fee - resource_fee < MIN_INCLUSION_FEE as i128 * operations as i128 {
+                return Err(anyhow!(
+                    "resource fee of {} stroops leaves no room for an inclusion fee under the cap of {} stroops",
+                    resource_fee,
+                    max_fee
+                ));
+            }
+            fee = fee.min(max_fee);
+        }
+        u32::try_from(fee).map_err(|_| anyhow!("fee of {} stroops is too large", fee))
+    }
+}
+
+/// Reads the soroban inclusion fee at `percentile` from a `getFeeStats` response.
+fn percentile_fee(stats: &Value, percentile: u32) -> Option<u32> {
+    let fees = &stats["sorobanInclusionFee"];
+    let key = match FEE_PERCENTILES.iter().find(|p| **p >= percentile) {
+        Some(p) => format!("p{}", p),
+        None => "max".to_string(),
+    };
+    // fees are strings of stroops, but accept numbers as well
+    match &fees[key] {
+        Value::String(fee) => fee.parse().ok(),
+        fee => fee.as_u64().map(|fee| fee.min(u32::MAX as u64) as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{percentile_fee, FeePolicy};
+    use serde_json::json;
+
+    #[test]
+    fn test_fee() {
+        let stats = json!({
+            "sorobanInclusionFee": { "max": "5000", "p90": "300", "p95": "1000", "p99": "2000" },
+        });
+        assert_eq!(percentile_fee(&stats, 90), Some(300));
+        assert_eq!(percentile_fee(&stats, 91), Some(1000));
+        assert_eq!(percentile_fee(&stats, 100), Some(5000));
+        assert_eq!(percentile_fee(&json!({}), 90), None);
+
+        let policy = FeePolicy::new().with_escalation_pct(50);
+        assert_eq!(policy.fee(300, 10_000, None, 0).unwrap(), 10_300);
+        // inclusion fees are never bid below the network minimum
+        assert_eq!(policy.fee(0, 10_000, None, 0).unwrap(), 10_100);
+        assert_eq!(policy.fee(400, 10_000, None, 2).unwrap(), 10_900);
+        assert_eq!(policy.fee(400, 10_000, Some(10_500), 2).unwrap(), 10_500);
+        assert!(policy.fee(400, 10_000, Some(10_050), 0).is_err());
+        assert!(policy.fee(400, u32::MAX as u64, None, 0).is_err());
+        assert_eq!(policy.fee_bump_fee(300, 10_000, None, 0).unwrap(), 10_600);
+        assert!(policy.fee_bump_fee(400, 10_000, Some(10_150), 0).is_err());
+    }
+}
@@ -1 +1,16 @@
 //! Utilities for working with Artemis.
+
+/// Exponential backoff schedules for retrying failed operations.
+pub mod backoff;
+
+/// Fee bids for soroban transactions based on network fee stats.
+pub mod fee_policy;
+
+/// Notifiers deliver operator alerts raised by the engine and its components.
+pub mod notifier;
+
+/// A soroban RPC client that fails over between several endpoints.
+pub mod rpc;
+
+/// Sequence numbers for sending several transactions from one account at once.
+pub mod sequence;
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::error;
+
+/// A destination for operator alerts, such as a chat webhook.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Deliver a message to the operator.
+    async fn notify(&self, message: &str) -> Result<()>;
+}
+
+/// A [Notifier](Notifier) that posts messages to a Slack incoming webhook.
+pub struct SlackNotifier {
+    webhook_url: String,
+}
+
+impl SlackNotifier {
+    pub fn new(webhook_url: &str) -> Self {
+        Self {
+            webhook_url: webhook_url.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, message: &str) -> Result<()> {
+        if self.webhook_url.is_empty() {
+            return Ok(());
+        }
+        reqwest::Client::new()
+            .post(self.webhook_url.clone())
+            .body(serde_json::json!({ "text": message }).to_string())
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+/// Sends a message through an optional notifier, logging delivery failures
+/// instead of returning them.
+pub async fn notify(notifier: &Option<Arc<dyn Notifier>>, message: &str) {
+    if let Some(notifier) = notifier {
+        if let Err(e) = notifier.notify(message).await {
+            error!("error sending notification: {}", e);
+        }
+    }
+}
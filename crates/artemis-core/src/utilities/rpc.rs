@@ -0,0 +1,323 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use jsonrpsee_core::{client::ClientT, params::ObjectParams};
+use serde::{de::DeserializeOwned, Serialize};
+use stellar_rpc_client::{
+    Assembled, Client, Error, EventStart, EventType, GetEventsResponse, GetLatestLedgerResponse,
+    GetLedgerEntriesResponse, SimulateTransactionResponse,
+};
+use stellar_xdr::curr::{AccountEntry, Hash, LedgerKey, Transaction, TransactionEnvelope};
+use tracing::warn;
+
+/// How much one ledger of lag behind the most advanced endpoint counts against an
+/// endpoint, in milliseconds of latency.
+const LAG_PENALTY_MS: u64 = 1_000;
+/// How much each consecutive error counts against an endpoint, in milliseconds of
+/// latency.
+const ERROR_PENALTY_MS: u64 = 10_000;
+
+/// What an [RpcClient](RpcClient) knows about one of its endpoints.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct EndpointHealth {
+    pub url: String,
+    /// Moving average of the request latency, once a request has succeeded.
+    pub latency_ms: Option<u64>,
+    pub requests: u64,
+    pub errors: u64,
+    /// Errors since the last successful request.
+    pub consecutive_errors: u32,
+    /// The latest ledger the endpoint reported, or 0 before it reported one.
+    pub latest_ledger: u32,
+}
+
+impl EndpointHealth {
+    /// Lower is healthier.
+    fn score(&self, best_ledger: u32) -> u64 {
+        let lag = best_ledger.saturating_sub(self.latest_ledger) as u64;
+        self.latency_ms.unwrap_or(0)
+            + lag * LAG_PENALTY_MS
+            + self.consecutive_errors as u64 * ERROR_PENALTY_MS
+    }
+
+    fn record(&mut self, latency: Duration, ok: bool) {
+        self.requests += 1;
+        if !ok {
+            self.errors += 1;
+            self.consecutive_errors += 1;
+            return;
+        }
+        self.consecutive_errors = 0;
+        let latency = latency.as_millis() as u64;
+        self.latency_ms = Some(match self.latency_ms {
+            Some(average) => (average * 4 + latency) / 5,
+            None => latency,
+        });
+    }
+}
+
+struct Endpoint {
+    client: Client,
+    health: Mutex<EndpointHealth>,
+}
+
+/// A soroban RPC client backed by several endpoints. Every request goes to the
+/// healthiest endpoint, judged by its latency, recent errors, and how far its
+/// latest ledger lags behind the others, and fails over to the next one if the
+/// endpoint can't be reached. Errors the endpoint answered with are returned as is.
+///
+/// Its methods mirror [Client](Client), so it can be used in its place.
+#[derive(Clone)]
+pub struct RpcClient {
+    endpoints: Arc<Vec<Endpoint>>,
+}
+
+impl RpcClient {
+    /// Creates a client for `urls`, preferring them in order while they are
+    /// equally healthy.
+    pub fn new(urls: &[impl AsRef<str>]) -> anyhow::Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow!("no rpc endpoints"));
+        }
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                Ok(Endpoint {
+                    client: Client::new(url.as_ref())?,
+                    health: Mutex::new(EndpointHealth {
+                        url: url.as_ref().to_string(),
+                        ..Default::default()
+                    }),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+        })
+    }
+
+    /// Polls every endpoint's latest ledger each `interval` so lagging endpoints
+    /// are noticed before they are used. The polling stops once every clone of
+    /// the client is dropped. Does nothing for a single endpoint.
+    pub fn with_health_checks(self, interval: Duration) -> Self {
+        if self.endpoints.len() > 1 {
+            let endpoints = Arc::downgrade(&self.endpoints);
+            tokio::spawn(health_checks(endpoints, interval));
+        }
+        self
+    }
+
+    /// Returns the health of every endpoint, in the order they were given.
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.health.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Returns the endpoint indexes from healthiest to least healthy.
+    fn ranked(&self) -> Vec<usize> {
+        let health = self.health();
+        let best_ledger = health.iter().map(|h| h.latest_ledger).max().unwrap_or(0);
+        let mut ranked: Vec<usize> = (0..health.len()).collect();
+        ranked.sort_by_key(|index| health[*index].score(best_ledger));
+        ranked
+    }
+
+    /// Runs `f` against the healthiest endpoint, failing over to the others while
+    /// they can't be reached.
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T, Error>
+    where
+        F: Fn(Client) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        Ok(self.call_endpoint(f).await?.1)
+    }
+
+    /// Like [call](RpcClient::call), but also returns the index of the endpoint
+    /// that answered.
+    async fn call_endpoint<T, F, Fut>(&self, f: F) -> Result<(usize, T), Error>
+    where
+        F: Fn(Client) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut last_error = None;
+        for index in self.ranked() {
+            let endpoint = &self.endpoints[index];
+            let start = Instant::now();
+            let res = f(endpoint.client.clone()).await;
+            let unreachable = res.as_ref().is_err_and(is_unreachable);
+            endpoint
+                .health
+                .lock()
+                .unwrap()
+                .record(start.elapsed(), !unreachable);
+            match res {
+                Err(e) if unreachable => {
+                    warn!("rpc endpoint {} failed: {}", endpoint.client.base_url(), e);
+                    last_error = Some(e);
+                }
+                res => return res.map(|res| (index, res)),
+            }
+        }
+        Err(last_error.unwrap_or(Error::InvalidResponse))
+    }
+
+    /// Sends a raw JSON-RPC request, for methods [Client](Client) doesn't wrap.
+    pub async fn request<R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: ObjectParams,
+    ) -> Result<R, Error> {
+        self.call(|client| {
+            let params = params.clone();
+            async move { Ok(client.client().request(method, params).await?) }
+        })
+        .await
+    }
+
+    pub async fn get_latest_ledger(&self) -> Result<GetLatestLedgerResponse, Error> {
+        let (index, latest) = self
+            .call_endpoint(|client| async move { client.get_latest_ledger().await })
+            .await?;
+        let mut health = self.endpoints[index].health.lock().unwrap();
+        health.latest_ledger = health.latest_ledger.max(latest.sequence);
+        Ok(latest)
+    }
+
+    pub async fn get_account(&self, address: &str) -> Result<AccountEntry, Error> {
+        self.call(|client| async move { client.get_account(address).await })
+            .await
+    }
+
+    pub async fn get_ledger_entries(
+        &self,
+        keys: &[LedgerKey],
+    ) -> Result<GetLedgerEntriesResponse, Error> {
+        self.call(|client| async move { client.get_ledger_entries(keys).await })
+            .await
+    }
+
+    pub async fn get_events(
+        &self,
+        start: EventStart,
+        event_type: Option<EventType>,
+        contract_ids: &[String],
+        topics: &[String],
+        limit: Option<usize>,
+    ) -> Result<GetEventsResponse, Error> {
+        self.call(|client| {
+            let start = start.clone();
+            async move {
+                client
+                    .get_events(start, event_type, contract_ids, topics, limit)
+                    .await
+            }
+        })
+        .await
+    }
+
+    pub async fn simulate_transaction_envelope(
+        &self,
+        tx: &TransactionEnvelope,
+    ) -> Result<SimulateTransactionResponse, Error> {
+        self.call(|client| async move { client.simulate_transaction_envelope(tx).await })
+            .await
+    }
+
+    pub async fn simulate_and_assemble_transaction(
+        &self,
+        tx: &Transaction,
+    ) -> Result<Assembled, Error> {
+        self.call(|client| async move { client.simulate_and_assemble_transaction(tx).await })
+            .await
+    }
+
+    /// Submits a transaction, failing over to another endpoint if the request
+    /// never got an answer. Resubmitting a signed transaction is safe, as it can
+    /// only be applied once.
+    pub async fn send_transaction(&self, tx: &TransactionEnvelope) -> Result<Hash, Error> {
+        self.call(|client| async move { client.send_transaction(tx).await })
+            .await
+    }
+}
+
+/// Whether a request failed because the endpoint couldn't be reached or answered
+/// with garbage, rather than with an error of its own.
+fn is_unreachable(e: &Error) -> bool {
+    match e {
+        Error::JsonRpc(jsonrpsee_core::Error::Call(_)) => false,
+        Error::JsonRpc(_) | Error::InvalidResponse | Error::Serde(_) | Error::MissingResult => true,
+        // the client wraps every failed send request, including the endpoint's own errors
+        Error::TransactionSubmissionFailed(msg) => {
+            msg.starts_with("No status yet") && !msg.contains("Call(")
+        }
+        _ => false,
+    }
+}
+
+async fn health_checks(endpoints: Weak<Vec<Endpoint>>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let Some(endpoints) = endpoints.upgrade() else {
+            return;
+        };
+        for endpoint in endpoints.iter() {
+            let start = Instant::now();
+            let res = endpoint.client.get_latest_ledger().await;
+            let mut health = endpoint.health.lock().unwrap();
+            health.record(start.elapsed(), res.is_ok());
+            if let Ok(latest) = res {
+                health.latest_ledger = health.latest_ledger.max(latest.sequence);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_unreachable, RpcClient};
+    use std::time::Duration;
+    use stellar_rpc_client::Error;
+
+    #[test]
+    fn test_ranks_healthiest_endpoint_first() {
+        let rpc = RpcClient::new(&["http://localhost:8001", "http://localhost:8002"]).unwrap();
+        assert_eq!(rpc.ranked(), vec![0, 1]);
+
+        // the primary lags ten ledgers behind
+        rpc.endpoints[0].health.lock().unwrap().latest_ledger = 90;
+        rpc.endpoints[1].health.lock().unwrap().latest_ledger = 100;
+        assert_eq!(rpc.ranked(), vec![1, 0]);
+
+        // the fallback starts failing
+        for _ in 0..2 {
+            rpc.endpoints[1]
+                .health
+                .lock()
+                .unwrap()
+                .record(Duration::from_millis(5), false);
+        }
+        assert_eq!(rpc.ranked(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_send_failures_fail_over_only_without_an_answer() {
+        let unanswered = Error::TransactionSubmissionFailed(
+            "No status yet:\n Transport(\n    error sending request,\n)".to_string(),
+        );
+        assert!(is_unreachable(&unanswered));
+        let rejected = Error::TransactionSubmissionFailed(
+            "No status yet:\n Call(\n    ErrorObject {\n        code: InvalidParams,\n    },\n)"
+                .to_string(),
+        );
+        assert!(!is_unreachable(&rejected));
+        let failed = Error::TransactionSubmissionFailed("TxBadSeq".to_string());
+        assert!(!is_unreachable(&failed));
+    }
+}
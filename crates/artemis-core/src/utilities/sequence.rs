@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::utilities::rpc::RpcClient;
+use anyhow::Result;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// The last sequence number handed out for an account, `None` if the next one
+/// has to be read from chain.
+type LastSequence = Arc<AsyncMutex<Option<i64>>>;
+
+/// Hands out the sequence numbers of the accounts transactions are sent from, so
+/// several transactions from one account can be in flight at once. An account's
+/// sequence number is read from chain once and counted locally after that, until
+/// a failure makes the count suspect and it is read again.
+#[derive(Clone, Default)]
+pub struct SequenceManager {
+    accounts: Arc<Mutex<HashMap<String, LastSequence>>>,
+    /// When the count of each account becomes suspect, in seconds since the
+    /// unix epoch, because a transaction holding one of its sequence numbers may
+    /// expire without being applied.
+    expiries: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+/// A sequence number reserved for a transaction. The account's next reservation
+/// waits until this one is dropped, so holding it until the transaction is sent
+/// sends the account's transactions in sequence order.
+pub struct SequenceReservation {
+    last: OwnedMutexGuard<Option<i64>>,
+    sequence: i64,
+}
+
+impl SequenceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn account(&self, account: &str) -> LastSequence {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(account.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Reserves the next sequence number of `account`, reading its current one
+    /// from chain if it isn't known.
+    pub async fn reserve(&self, rpc: &RpcClient, account: &str) -> Result<SequenceReservation> {
+        let mut last = self.account(account).lock_owned().await;
+        if self.expired(account) {
+            *last = None;
+        }
+        let current = match *last {
+            Some(last) => last,
+            None => rpc.get_account(account).await?.seq_num.into(),
+        };
+        *last = Some(current + 1);
+        Ok(SequenceReservation {
+            last,
+            sequence: current + 1,
+        })
+    }
+
+    /// Has the sequence number of `account` read from chain again once it's
+    /// `expires_at` seconds since the unix epoch, when a transaction that may
+    /// not be applied can't be anymore. The account's reservations are counted
+    /// on locally until then.
+    pub fn expire(&self, account: &str, expires_at: u64) {
+        let mut expiries = self.expiries.lock().unwrap();
+        let expiry = expiries.entry(account.to_string()).or_default();
+        *expiry = (*expiry).max(expires_at);
+    }
+
+    /// Whether the count of `account` became suspect, clearing its expiry if so.
+    fn expired(&self, account: &str) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut expiries = self.expiries.lock().unwrap();
+        match expiries.get(account) {
+            Some(expires_at) if *expires_at <= now => {
+                expiries.remove(account);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the sequence number of `account`, so the next one is read from
+    /// chain. Transactions still in flight may then have their sequence numbers
+    /// handed out again, so this is for when they are likely to fail anyway.
+    pub async fn resync(&self, account: &str) {
+        *self.account(account).lock().await = None;
+    }
+}
+
+impl SequenceReservation {
+    pub fn sequence(&self) -> i64 {
+        self.sequence
+    }
+
+    /// Gives up the sequence number because the transaction couldn't be sent,
+    /// and has the next one read from chain.
+    pub fn resync(mut self) {
+        *self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SequenceManager;
+    use crate::utilities::rpc::RpcClient;
+
+    #[tokio::test]
+    async fn test_reserve() {
+        let rpc = RpcClient::new(&["http://localhost:8001"]).unwrap();
+        let sequences = SequenceManager::new();
+        *sequences.account("GA").lock().await = Some(10);
+
+        let first = sequences.reserve(&rpc, "GA").await.unwrap();
+        assert_eq!(first.sequence(), 11);
+        drop(first);
+        let second = sequences.reserve(&rpc, "GA").await.unwrap();
+        assert_eq!(second.sequence(), 12);
+
+        // the next reservation waits for the last one to be sent
+        let pending = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            sequences.reserve(&rpc, "GA"),
+        );
+        assert!(pending.await.is_err());
+
+        second.resync();
+        assert_eq!(*sequences.account("GA").lock().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_expire() {
+        let rpc = RpcClient::new(&["http://localhost:8001"]).unwrap();
+        let sequences = SequenceManager::new();
+        *sequences.account("GA").lock().await = Some(10);
+
+        // counting goes on until the expiry passes
+        sequences.expire("GA", u64::MAX);
+        assert!(!sequences.expired("GA"));
+        assert_eq!(sequences.reserve(&rpc, "GA").await.unwrap().sequence(), 11);
+
+        // then the count is read from chain again, which fails without an rpc
+        sequences
+            .expiries
+            .lock()
+            .unwrap()
+            .insert("GA".to_string(), 0);
+        assert!(sequences.reserve(&rpc, "GA").await.is_err());
+        assert!(!sequences.expired("GA"));
+    }
+}
@@ -23,6 +23,10 @@ pub struct OngoingAuction {
     pub min_profit_pct: i128,
     pub db_manager: DbManager,
     pub block_submitted: u32,
+    /// The action id of the latest fill submitted for this auction, if any.
+    pub submitted_action_id: Option<String>,
+    /// The ledger of the latest fill event processed for this auction.
+    pub last_fill_ledger: u32,
 }
 
 impl OngoingAuction {
@@ -45,8 +49,19 @@ impl OngoingAuction {
             min_profit_pct,
             db_manager,
             block_submitted: 0,
+            submitted_action_id: None,
+            last_fill_ledger: 0,
         }
     }
+
+    /// The id of the fill action submitted for this auction in `ledger`, unique
+    /// to that submission.
+    pub fn action_id(&self, ledger: u32) -> String {
+        format!(
+            "fill:{}:{}:{}:{}",
+            self.pool, self.user, self.auction_type, ledger
+        )
+    }
     pub fn calc_liquidation_fill(
         &mut self,
         our_positions: &UserPositions,
@@ -238,7 +253,7 @@ impl OngoingAuction {
         supported_collateral: &Vec<String>,
         min_hf: &i128,
         submit_block: u32,
-        xlm_id: &String,
+        fee_reserves: &HashMap<String, i128>,
     ) -> Result<Vec<Request>> {
         let mut new_pool_positions = pool_position.clone();
         let mut requests: Vec<Request> = vec![Request {
@@ -261,9 +276,9 @@ impl OngoingAuction {
                         .get_reserve_config_from_asset(&self.pool, &bid_asset)?;
                     let mut wallet_balance = wallet.get(&bid_asset).unwrap().clone();
 
-                    // If the bid asset is XLM we need to reserve for the transaction fees
-                    if bid_asset == *xlm_id {
-                        wallet_balance -= 100 * SCALAR_7;
+                    // If the bid asset pays the transaction fees we need to reserve for them
+                    if let Some(fee_reserve) = fee_reserves.get(&bid_asset) {
+                        wallet_balance -= fee_reserve;
                         if wallet_balance > 0 {
                             requests.push(Request {
                                 request_type: 5,
@@ -1,11 +1,12 @@
 use anyhow::Result;
 use artemis_core::{
-    collectors::block_collector::NewBlock, executors::soroban_executor::SubmitStellarTx,
-    types::Strategy,
+    collectors::block_collector::NewBlock, delivery::Interest,
+    executors::soroban_executor::SubmitStellarTx, types::Strategy, utilities::rpc::RpcClient,
 };
 use soroban_spec_tools::from_string_primitive;
 
 use crate::{
+    backfill::UserBackfill,
     db_manager::DbManager,
     file_logger::{heartbeat, log_error},
     helper::{
@@ -13,12 +14,12 @@ use crate::{
         load_reserve_configs, update_rate, user_positions_from_ledger_entry,
     },
     transaction_builder::BlendTxBuilder,
-    types::{Action, Config, Event, UserPositions},
+    types::{Action, Config, Event, EventInterest, UserPositions},
 };
 use async_trait::async_trait;
 use ed25519_dalek::SigningKey;
-use std::{collections::HashMap, str::FromStr, thread::sleep, time::Duration, vec};
-use stellar_rpc_client::{Client, Event as SorobanEvent};
+use std::{collections::HashMap, path::Path, str::FromStr, thread::sleep, time::Duration, vec};
+use stellar_rpc_client::Event as SorobanEvent;
 use stellar_xdr::curr::{
     AccountId, LedgerEntryData, LedgerKeyContractData, Limits, PublicKey, ReadXdr, ScAddress,
     ScMap, ScMapEntry, ScSpecTypeDef, ScSymbol, ScVal, ScVec, StringM, Uint256, VecM,
@@ -27,7 +28,7 @@ use tracing::{error, info};
 
 pub struct BlendAuctioneer {
     /// Soroban RPC client for interacting with chain
-    rpc: Client,
+    rpc: RpcClient,
     /// The path to the database directory
     db_manager: DbManager,
     /// The supported collateral assets
@@ -36,6 +37,8 @@ pub struct BlendAuctioneer {
     supported_liabilities: Vec<String>,
     /// Vec of Blend pool addresses to create auctions for
     pools: Vec<String>,
+    /// The events routed to us, which adopted pools are added to
+    event_interest: EventInterest,
     /// Map pool users and their positions
     /// - only stores users with health factor < 5
     /// - only stores users with relevant assets
@@ -51,18 +54,19 @@ pub struct BlendAuctioneer {
     oracle_id: String,
     // Oracle Decimals
     oracle_decimals: u32,
+    /// Whether a price changed since users were last evaluated
+    prices_changed: bool,
 }
 
 impl BlendAuctioneer {
-    pub async fn new(config: &Config, signing_key: &SigningKey) -> Result<Self> {
-        let client = Client::new(config.rpc_url.as_str())?;
-
+    pub async fn new(rpc: RpcClient, config: &Config, signing_key: &SigningKey) -> Result<Self> {
         Ok(Self {
-            rpc: client,
+            rpc,
             db_manager: DbManager::new(config.db_path.clone()),
             supported_collateral: config.supported_collateral.clone(),
             supported_liabilities: config.supported_liabilities.clone(),
             pools: config.pools.clone(),
+            event_interest: EventInterest::new(),
             users: HashMap::new(),
             us: signing_key.clone(),
             us_public: ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(
@@ -72,6 +76,7 @@ impl BlendAuctioneer {
             backstop_token_address: config.backstop_token_address.clone(),
             oracle_id: config.oracle_id.clone(),
             oracle_decimals: config.oracle_decimals,
+            prices_changed: false,
         })
     }
 }
@@ -79,46 +84,13 @@ impl BlendAuctioneer {
 #[async_trait]
 impl Strategy<Event, Action> for BlendAuctioneer {
     async fn sync_state(&mut self) -> Result<()> {
+        // rebuild tracked users from chain so a resync drops stale positions
+        self.users.clear();
         for pool in &self.pools {
-            let assets = get_reserve_list(&self.rpc, pool).await?;
-            self.db_manager.initialize(&assets)?;
-            get_asset_prices_db(
-                &self.rpc,
-                &self.oracle_id,
-                &self.oracle_decimals,
-                &assets,
-                &self.db_manager,
-            )
-            .await?;
-            load_reserve_configs(&self.rpc, pool, &assets, &self.db_manager)
-                .await
-                .unwrap();
+            self.sync_pool(pool).await?;
         }
-
-        let users = self.db_manager.get_users()?;
-        for user in users {
-            for pool in self.pools.clone() {
-                match self.user_has_liquidation(pool.clone(), user.as_str()).await {
-                    Ok(res) => {
-                        if res {
-                            continue;
-                        }
-                    }
-                    Err(err) => error!(
-                        "Failed to check {} for liquidation auction with error: {}",
-                        user.clone(),
-                        err
-                    ),
-                }
-                match self.get_user_position(pool.clone(), user.as_str()).await {
-                    Ok(_) => (),
-                    Err(err) => error!(
-                        "Failed to get positions for user {} with error: {}",
-                        user.clone(),
-                        err
-                    ),
-                }
-            }
+        for pool in self.pools.clone() {
+            self.sync_pool_users(&pool).await?;
         }
         info!("synced auctioneer state");
         Ok(())
@@ -168,13 +140,154 @@ impl Strategy<Event, Action> for BlendAuctioneer {
                         }
                     }
                 }
+                // failed auction submissions are retried by the periodic user evaluation
+                Event::ExecutionResult(_) => return Vec::new(),
+                // the auctioneer watches no ledger entries and holds no tokens
+                Event::LedgerEntryChange(_) | Event::BalanceUpdate(_) | Event::AccountEvent(_) => {
+                    return Vec::new()
+                }
+                Event::PriceUpdate(ref update) => {
+                    if let Err(e) = self
+                        .db_manager
+                        .set_asset_price(update.asset.clone(), update.price)
+                    {
+                        error!("Error storing price for asset {}: {}", update.asset, e);
+                    }
+                    self.prices_changed = true;
+                    return Vec::new();
+                }
+                Event::PoolDiscovered(ref discovered) => {
+                    if let Err(e) = self.adopt_pool(&discovered.pool, discovered.ledger).await {
+                        error!("Error syncing discovered pool {}: {}", discovered.pool, e);
+                    }
+                    return Vec::new();
+                }
+                Event::EventGap(ref gap) => {
+                    info!(
+                        "Auctioneer missed events from ledger {} to {}, resyncing",
+                        gap.from_ledger, gap.to_ledger
+                    );
+                    if let Err(e) = self.sync_state().await {
+                        error!("Error resyncing after event gap: {}", e);
+                    }
+                    return Vec::new();
+                }
             }
         }
         return Vec::new();
     }
+
+    fn interest(&self) -> Interest<Event> {
+        self.event_interest
+            .clone()
+            .with_new_blocks()
+            .with_price_updates()
+            .with_pool_discovery()
+            .with_soroban_events(
+                &self.pools,
+                &[
+                    "new_liquidation_auction",
+                    "delete_liquidation_auction",
+                    "fill_auction",
+                    "bad_debt",
+                    "set_reserve",
+                    "supply",
+                    "withdraw",
+                    "supply_collateral",
+                    "withdraw_collateral",
+                    "borrow",
+                    "repay",
+                ],
+            )
+            .into()
+    }
+
+    async fn on_lag(&mut self, skipped: u64) -> Result<()> {
+        // skipped position events leave tracked users stale
+        info!("Auctioneer missed {} events, resyncing", skipped);
+        self.sync_state().await
+    }
 }
 
 impl BlendAuctioneer {
+    /// Loads a pool's reserve configs and asset prices into the db.
+    async fn sync_pool(&self, pool: &String) -> Result<()> {
+        let assets = get_reserve_list(&self.rpc, pool).await?;
+        self.db_manager.initialize(&assets)?;
+        get_asset_prices_db(
+            &self.rpc,
+            &self.oracle_id,
+            &self.oracle_decimals,
+            &assets,
+            &self.db_manager,
+        )
+        .await?;
+        load_reserve_configs(&self.rpc, pool, &assets, &self.db_manager)
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    /// Loads the positions every known user holds in a pool.
+    async fn sync_pool_users(&mut self, pool: &str) -> Result<()> {
+        let users = self.db_manager.get_users()?;
+        for user in users {
+            match self
+                .user_has_liquidation(pool.to_string(), user.as_str())
+                .await
+            {
+                Ok(res) => {
+                    if res {
+                        continue;
+                    }
+                }
+                Err(err) => error!(
+                    "Failed to check {} for liquidation auction with error: {}",
+                    user.clone(),
+                    err
+                ),
+            }
+            match self
+                .get_user_position(pool.to_string(), user.as_str())
+                .await
+            {
+                Ok(_) => (),
+                Err(err) => error!(
+                    "Failed to get positions for user {} with error: {}",
+                    user.clone(),
+                    err
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts tracking a pool discovered at `ledger`. The pool is kept even if
+    /// syncing it fails, so the next resync picks it up.
+    async fn adopt_pool(&mut self, pool: &String, ledger: u32) -> Result<()> {
+        if self.pools.contains(pool) {
+            return Ok(());
+        }
+        info!("Auctioneer adopting pool {}", pool);
+        self.pools.push(pool.clone());
+        // route the pool's events to us first, then pick up the users of any
+        // events that were filtered out before
+        self.event_interest.add_contract_id(pool);
+        let backfill = UserBackfill::new(
+            self.rpc.clone(),
+            vec![pool.clone()],
+            self.db_manager.clone(),
+        )
+        .with_cursor_path(
+            Path::new(&self.db_manager.db_directory).join(format!("backfill_{}.json", pool)),
+        );
+        if let Err(e) = backfill.run(ledger).await {
+            error!("Error backfilling users of discovered pool {}: {}", pool, e);
+        }
+        self.sync_pool(pool).await?;
+        self.sync_pool_users(pool).await
+    }
+
     // Process new orders as they come in.
     async fn process_soroban_events(&mut self, event: SorobanEvent) -> Result<Vec<Action>> {
         let mut actions = Vec::new();
@@ -297,10 +410,12 @@ impl BlendAuctioneer {
                     signing_key: self.us.clone(),
                 };
                 actions.push(Action::SubmitTx(SubmitStellarTx {
+                    action_id: format!("new_bad_debt_auction:{}", pool_id),
                     op: tx_builder.new_bad_debt_auction(),
                     gas_bid_info: None,
                     signing_key: self.us.clone(),
                     max_retries: 10,
+                    target_ledger: None,
                 }));
             }
             "set_reserve" => {
@@ -696,19 +811,28 @@ impl BlendAuctioneer {
     /// Process new block events, updating the internal state.
     async fn process_new_block_event(&mut self, event: NewBlock) -> Result<Vec<Action>> {
         let mut actions = Vec::new();
-        let mut assets = self.supported_collateral.clone();
-        assets.extend(self.supported_liabilities.clone());
-        if event.number % 10 == 0 {
-            get_asset_prices_db(
+        // every 10 blocks prices are read from the oracle, in case the price
+        // collector stopped, and users are evaluated for accrued interest and
+        // failed auction submissions
+        let periodic = event.number % 10 == 0;
+        if periodic {
+            if let Err(e) = get_asset_prices_db(
                 &self.rpc,
                 &self.oracle_id,
                 &self.oracle_decimals,
                 &self.supported_liabilities,
                 &self.db_manager,
             )
-            .await?;
+            .await
+            {
+                error!("Failed to read prices from the oracle: {}", e);
+            }
+        }
+        // price updates are batched per ledger, so users are evaluated once per oracle move
+        if self.prices_changed || periodic {
+            self.prices_changed = false;
 
-            // evalaute users ever 10 blocks for potential liquidations
+            // evaluate users for potential liquidations
             for pool in self.pools.iter() {
                 for users in self.users.get(pool).iter_mut() {
                     for user in users.iter() {
@@ -721,7 +845,7 @@ impl BlendAuctioneer {
                         ) {
                             Ok(score) => {
                                 // create liquidation auction if needed
-                                let action = self.act_on_score(&user.0, &pool, score);
+                                let action = self.act_on_score(user.0, pool, score);
                                 if action.is_some() {
                                     info!("Creating liquidation auction for user: {}", user.0);
                                     actions.push(action.unwrap());
@@ -756,10 +880,7 @@ impl BlendAuctioneer {
                 key: reserve_data_key,
                 durability: stellar_xdr::curr::ContractDataDurability::Persistent,
             });
-        let result = self
-            .rpc
-            .get_ledger_entries(&vec![position_ledger_key])
-            .await?;
+        let result = self.rpc.get_ledger_entries(&[position_ledger_key]).await?;
         if let Some(entries) = result.entries {
             for entry in entries {
                 let value: LedgerEntryData =
@@ -825,10 +946,7 @@ impl BlendAuctioneer {
                 key: reserve_data_key,
                 durability: stellar_xdr::curr::ContractDataDurability::Temporary,
             });
-        let result = self
-            .rpc
-            .get_ledger_entries(&vec![position_ledger_key])
-            .await?;
+        let result = self.rpc.get_ledger_entries(&[position_ledger_key]).await?;
         if let Some(entries) = result.entries {
             for entry in entries {
                 let value: LedgerEntryData =
@@ -858,20 +976,24 @@ impl BlendAuctioneer {
         if score == 0 {
             // Code to execute if the value is None
             return Some(Action::SubmitTx(SubmitStellarTx {
+                action_id: format!("bad_debt:{}:{}", pool, user),
                 op: tx_builder.bad_debt(user),
                 gas_bid_info: None,
                 signing_key: self.us.clone(),
                 max_retries: 10,
+                target_ledger: None,
             }));
         }
 
         if score > 2 {
             // Code to execute if the value is None
             return Some(Action::SubmitTx(SubmitStellarTx {
+                action_id: format!("new_liquidation_auction:{}:{}", pool, user),
                 op: tx_builder.new_liquidation_auction(user, score),
                 gas_bid_info: None,
                 signing_key: self.us.clone(),
                 max_retries: 10,
+                target_ledger: None,
             }));
         }
         None
@@ -0,0 +1,244 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use artemis_core::utilities::rpc::RpcClient;
+use serde::{Deserialize, Serialize};
+use stellar_rpc_client::{Event as SorobanEvent, EventStart, EventType};
+use stellar_xdr::curr::{Limits, ReadXdr, ScVal};
+use tracing::info;
+
+use crate::db_manager::DbManager;
+
+/// The pool events that name the user whose position changed as their third topic.
+const POSITION_EVENTS: [&str; 6] = [
+    "supply",
+    "withdraw",
+    "supply_collateral",
+    "withdraw_collateral",
+    "borrow",
+    "repay",
+];
+
+/// Walks the historical events of a set of pools and adds every user that changed
+/// a position to the users db, so users that were active before the bot started
+/// are tracked too. Progress is saved after every page, and a backfill that was
+/// interrupted resumes from where it stopped, unless it walked other pools.
+pub struct UserBackfill {
+    rpc: RpcClient,
+    pools: Vec<String>,
+    db_manager: DbManager,
+    cursor_path: PathBuf,
+    page_limit: usize,
+}
+
+/// How far a backfill got.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct BackfillCursor {
+    /// The pools walked, sorted.
+    #[serde(default)]
+    pools: Vec<String>,
+    ledger: u32,
+    paging_token: Option<String>,
+}
+
+impl BackfillCursor {
+    /// Reads the cursor at `path`, or `None` if none was saved yet.
+    fn load(path: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the cursor to `path`, replacing the previous one atomically.
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+impl UserBackfill {
+    /// Creates a backfill of `pools` that saves its progress to
+    /// `backfill_cursor.json` in the db directory.
+    pub fn new(rpc: RpcClient, pools: Vec<String>, db_manager: DbManager) -> Self {
+        let cursor_path = Path::new(&db_manager.db_directory).join("backfill_cursor.json");
+        Self {
+            rpc,
+            pools,
+            db_manager,
+            cursor_path,
+            page_limit: 200,
+        }
+    }
+
+    /// Saves the backfill's progress to `path` instead.
+    pub fn with_cursor_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cursor_path = path.into();
+        self
+    }
+
+    /// Sets the most events requested at once.
+    pub fn with_page_limit(mut self, page_limit: usize) -> Self {
+        self.page_limit = page_limit;
+        self
+    }
+
+    /// Returns where to start: where a previous backfill of the same pools
+    /// stopped, or `start_ledger`.
+    fn start(&self, start_ledger: u32) -> Result<BackfillCursor> {
+        let mut pools = self.pools.clone();
+        pools.sort();
+        match BackfillCursor::load(&self.cursor_path)? {
+            Some(cursor) if cursor.pools == pools => {
+                info!("resuming user backfill from ledger {}", cursor.ledger);
+                Ok(cursor)
+            }
+            previous => {
+                if previous.is_some() {
+                    info!(
+                        "pools changed, restarting user backfill from ledger {}",
+                        start_ledger
+                    );
+                }
+                Ok(BackfillCursor {
+                    pools,
+                    ledger: start_ledger,
+                    paging_token: None,
+                })
+            }
+        }
+    }
+
+    /// Adds the users of every position event from `start_ledger`, or from where a
+    /// previous backfill of the same pools stopped, up to the latest ledger.
+    /// Returns the number of distinct users seen.
+    pub async fn run(&self, start_ledger: u32) -> Result<usize> {
+        self.db_manager.initialize(&vec![])?;
+        let mut cursor = self.start(start_ledger)?;
+        let mut users = HashSet::new();
+        loop {
+            let start = match cursor.paging_token.clone() {
+                Some(paging_token) => EventStart::Cursor(paging_token),
+                None => EventStart::Ledger(cursor.ledger),
+            };
+            let page = self
+                .rpc
+                .get_events(
+                    start,
+                    Some(EventType::Contract),
+                    &self.pools,
+                    &[],
+                    Some(self.page_limit),
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "error fetching events from ledger {}, it may be older than the rpc retains",
+                        cursor.ledger
+                    )
+                })?;
+            let caught_up = page.events.len() < self.page_limit;
+            for event in &page.events {
+                if let Some(user) = position_user(event) {
+                    self.db_manager.add_user(&user)?;
+                    users.insert(user);
+                }
+                cursor.ledger = event.ledger;
+                cursor.paging_token = Some(event.paging_token.clone());
+            }
+            if page.events.is_empty() && cursor.paging_token.is_none() {
+                cursor.ledger = cursor.ledger.max(page.latest_ledger);
+            }
+            cursor.save(&self.cursor_path)?;
+            info!(
+                "backfilled users to ledger {} of {}, {} users found",
+                cursor.ledger,
+                page.latest_ledger,
+                users.len()
+            );
+            if caught_up {
+                return Ok(users.len());
+            }
+        }
+    }
+}
+
+/// Returns the user of a position event, or `None` for any other event.
+fn position_user(event: &SorobanEvent) -> Option<String> {
+    let name = ScVal::from_xdr_base64(event.topic.first()?, Limits::none()).ok()?;
+    match name {
+        ScVal::Symbol(name) if POSITION_EVENTS.contains(&name.to_utf8_string_lossy().as_str()) => {}
+        _ => return None,
+    }
+    match ScVal::from_xdr_base64(event.topic.get(2)?, Limits::none()).ok()? {
+        ScVal::Address(user) => Some(user.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{position_user, UserBackfill};
+    use crate::db_manager::DbManager;
+    use artemis_core::utilities::rpc::RpcClient;
+    use stellar_rpc_client::Event;
+    use stellar_xdr::curr::{Hash, Limits, ScAddress, ScSymbol, ScVal, WriteXdr};
+
+    fn event(name: &str) -> Event {
+        let address = |byte| ScVal::Address(ScAddress::Contract(Hash([byte; 32])));
+        let topic = [
+            ScVal::Symbol(ScSymbol(name.try_into().unwrap())),
+            address(1),
+            address(2),
+        ];
+        Event {
+            event_type: "contract".to_string(),
+            ledger: 1,
+            ledger_closed_at: String::new(),
+            id: String::new(),
+            paging_token: String::new(),
+            contract_id: String::new(),
+            topic: topic
+                .iter()
+                .map(|topic| topic.to_xdr_base64(Limits::none()).unwrap())
+                .collect(),
+            value: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_position_user() {
+        let user = ScAddress::Contract(Hash([2; 32])).to_string();
+        assert_eq!(position_user(&event("supply_collateral")), Some(user));
+        assert_eq!(position_user(&event("fill_auction")), None);
+    }
+
+    #[test]
+    fn test_start_resumes_only_the_same_pools() {
+        let path = std::env::temp_dir().join(format!("backfill_{}.json", std::process::id()));
+        let backfill = |pools: &[&str]| {
+            UserBackfill::new(
+                RpcClient::new(&["http://localhost:8001"]).unwrap(),
+                pools.iter().map(|pool| pool.to_string()).collect(),
+                DbManager::new(String::new()),
+            )
+            .with_cursor_path(&path)
+        };
+        let mut cursor = backfill(&["b", "a"]).start(10).unwrap();
+        assert_eq!(cursor.pools, vec!["a", "b"]);
+        cursor.ledger = 20;
+        cursor.save(&path).unwrap();
+
+        assert_eq!(backfill(&["a", "b"]).start(10).unwrap().ledger, 20);
+        assert_eq!(backfill(&["a", "c"]).start(10).unwrap().ledger, 10);
+        std::fs::remove_file(path).unwrap();
+    }
+}
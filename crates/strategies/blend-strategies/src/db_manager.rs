@@ -47,6 +47,13 @@ impl DbManager {
             [],
         )?;
 
+        db.execute(
+            "create table if not exists discovered_pools (
+                address string primary key
+             )",
+            [],
+        )?;
+
         let placeholder_int = 1i64;
         for asset in assets.clone() {
             let result = db.execute(
@@ -249,6 +256,29 @@ impl DbManager {
         db.close().unwrap();
         Ok(())
     }
+    /// Returns the pools adopted by pool discovery.
+    pub fn get_discovered_pools(&self) -> Result<Vec<String>> {
+        let db = Connection::open(&self.blend_asset_path)?;
+        let pools = {
+            let mut stmt = db.prepare("SELECT address FROM discovered_pools")?;
+            let pools = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            pools.collect::<Result<Vec<_>, _>>()?
+        };
+        db.close().unwrap();
+        Ok(pools)
+    }
+
+    /// Records a pool adopted by pool discovery, so it is tracked after a restart.
+    pub fn add_discovered_pool(&self, pool: &str) -> Result<()> {
+        let db = Connection::open(&self.blend_asset_path)?;
+        db.execute(
+            "INSERT OR IGNORE INTO discovered_pools (address) VALUES (?1)",
+            [pool],
+        )?;
+        db.close().unwrap();
+        Ok(())
+    }
+
     pub fn add_auction(
         &self,
         auction_data: &AuctionData,
@@ -8,11 +8,11 @@ use crate::{
     types::{AuctionData, ReserveConfig, UserPositions},
 };
 use anyhow::{Error, Result};
+use artemis_core::utilities::rpc::RpcClient;
 use ed25519_dalek::SigningKey;
 use serde_json;
 use soroban_fixed_point_math::FixedPoint;
 use soroban_spec_tools::from_string_primitive;
-use stellar_rpc_client::Client;
 use stellar_xdr::curr::{
     InvokeContractArgs, InvokeHostFunctionOp, LedgerEntryData, LedgerKey, LedgerKeyContractData,
     Limits, Memo, MuxedAccount, Operation, Preconditions, ReadXdr, ScAddress, ScSpecTypeDef,
@@ -395,7 +395,7 @@ fn get_liq_percent(
 }
 
 pub async fn bstop_token_to_usdc(
-    rpc: &Client,
+    rpc: &RpcClient,
     bstop_tkn_address: String,
     backstop: String,
     lp_amount: i128,
@@ -482,12 +482,8 @@ pub async fn bstop_token_to_usdc(
     return Ok(usdc_out.unwrap());
 }
 
-pub async fn get_pool_positions(
-    rpc: &Client,
-    pool: &String,
-    user: &String,
-    db_manager: &DbManager,
-) -> Result<Option<UserPositions>> {
+// Gets the ledger key of a user's positions in a pool
+pub fn positions_ledger_key(pool: &str, user: &str) -> Result<LedgerKey> {
     let reserve_data_key = ScVal::Vec(Some(
         ScVec::try_from(vec![
             ScVal::Symbol(ScSymbol::from(ScSymbol::from(
@@ -497,33 +493,35 @@ pub async fn get_pool_positions(
         ])
         .unwrap(),
     ));
-    let position_ledger_key = stellar_xdr::curr::LedgerKey::ContractData(LedgerKeyContractData {
+    Ok(LedgerKey::ContractData(LedgerKeyContractData {
         contract: ScAddress::from_str(pool)?,
         key: reserve_data_key,
         durability: stellar_xdr::curr::ContractDataDurability::Persistent,
-    });
-    let result = rpc.get_ledger_entries(&vec![position_ledger_key]).await?;
-    if let Some(entries) = result.entries {
-        for entry in entries {
-            let value: LedgerEntryData =
-                LedgerEntryData::from_xdr_base64(entry.xdr, Limits::none()).unwrap();
-
-            match &value {
-                LedgerEntryData::ContractData(_) => {
-                    let user_position =
-                        user_positions_from_ledger_entry(&value, pool, &db_manager)?;
+    }))
+}
 
-                    return Ok(Some(user_position));
-                }
-                _ => return Ok(None),
-            }
-        }
+pub async fn get_pool_positions(
+    rpc: &RpcClient,
+    pool: &String,
+    user: &String,
+    db_manager: &DbManager,
+) -> Result<Option<UserPositions>> {
+    let position_ledger_key = positions_ledger_key(pool, user)?;
+    let result = rpc.get_ledger_entries(&[position_ledger_key]).await?;
+    let Some(entry) = result.entries.unwrap_or_default().into_iter().next() else {
+        return Ok(None);
+    };
+    let value = LedgerEntryData::from_xdr_base64(entry.xdr, Limits::none())?;
+    if let LedgerEntryData::ContractData(_) = &value {
+        return Ok(Some(user_positions_from_ledger_entry(
+            &value, pool, db_manager,
+        )?));
     }
     Ok(None)
 }
 
 // Gets balance of an asset
-pub async fn get_balance(rpc: &Client, user: String, asset: String) -> Result<i128> {
+pub async fn get_balance(rpc: &RpcClient, user: String, asset: String) -> Result<i128> {
     // A random key is fine for simulation
     let key = SigningKey::from_bytes(&[0; 32]);
     let op = BlendTxBuilder {
@@ -562,7 +560,7 @@ pub async fn get_balance(rpc: &Client, user: String, asset: String) -> Result<i1
 }
 
 // Gets total comet tokens
-pub async fn total_comet_tokens(rpc: &Client, bstop_tkn_address: String) -> Result<i128> {
+pub async fn total_comet_tokens(rpc: &RpcClient, bstop_tkn_address: String) -> Result<i128> {
     // A random key is fine for simulation
     let key = SigningKey::from_bytes(&[0; 32]);
 
@@ -600,6 +598,45 @@ pub async fn total_comet_tokens(rpc: &Client, bstop_tkn_address: String) -> Resu
     };
 }
 
+// Gets a token's name, "CODE:ISSUER" or "native" for a stellar asset contract
+pub async fn get_token_name(rpc: &RpcClient, token: &str) -> Result<String> {
+    // A random key is fine for simulation
+    let key = SigningKey::from_bytes(&[0; 32]);
+
+    let op = Operation {
+        source_account: None,
+        body: stellar_xdr::curr::OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+            host_function: stellar_xdr::curr::HostFunction::InvokeContract(InvokeContractArgs {
+                contract_address: ScAddress::from_str(token)?,
+                function_name: ScSymbol::try_from("name").unwrap(),
+                args: VecM::default(),
+            }),
+            auth: VecM::default(),
+        }),
+    };
+    let transaction: TransactionEnvelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+        tx: Transaction {
+            source_account: MuxedAccount::Ed25519(Uint256(key.verifying_key().to_bytes())),
+            fee: 10000,
+            seq_num: stellar_xdr::curr::SequenceNumber(10),
+            cond: Preconditions::None,
+            memo: Memo::None,
+            operations: vec![op].try_into()?,
+            ext: stellar_xdr::curr::TransactionExt::V0,
+        },
+        signatures: VecM::default(),
+    });
+    let sim_result = rpc.simulate_transaction_envelope(&transaction).await?;
+    let result = sim_result
+        .results
+        .first()
+        .ok_or_else(|| Error::msg("Error: failed to get token name"))?;
+    match ScVal::from_xdr_base64(result.xdr.clone(), Limits::none())? {
+        ScVal::String(name) => Ok(name.to_utf8_string_lossy()),
+        _ => Err(Error::msg("Error: failed to get token name")),
+    }
+}
+
 pub fn update_rate(numerator: i128, denominator: i128) -> Result<i128> {
     let rate = numerator
         .fixed_div_floor(denominator, SCALAR_9)
@@ -612,66 +649,81 @@ pub fn update_rate(numerator: i128, denominator: i128) -> Result<i128> {
 }
 
 pub async fn get_asset_prices_db(
-    rpc: &Client,
+    rpc: &RpcClient,
     oracle_id: &String,
     oracle_decimals: &u32,
     assets: &Vec<String>,
     db_manager: &DbManager,
 ) -> Result<()> {
-    // A random key is fine for simulation
-    let key = SigningKey::from_bytes(&[0; 32]);
     // get asset prices from oracle
     for asset in assets.iter() {
-        let tx_builder = BlendTxBuilder {
-            contract_id: oracle_id.clone(),
-            signing_key: key.clone(),
-        };
-        let op = tx_builder.get_last_price(asset);
-        let transaction: TransactionEnvelope = TransactionEnvelope::Tx(TransactionV1Envelope {
-            tx: Transaction {
-                source_account: MuxedAccount::Ed25519(Uint256(key.verifying_key().to_bytes())),
-                fee: 10000,
-                seq_num: stellar_xdr::curr::SequenceNumber(10),
-                cond: Preconditions::None,
-                memo: Memo::None,
-                operations: vec![op].try_into()?,
-                ext: stellar_xdr::curr::TransactionExt::V0,
-            },
-            signatures: VecM::default(),
-        });
-        let sim_result = rpc.simulate_transaction_envelope(&transaction).await?;
-        if sim_result.results.is_empty() {
-            error!("Error: failed to get price for asset {}", asset);
-            continue;
+        match get_last_price(rpc, oracle_id, oracle_decimals, asset).await? {
+            Some((price, _)) => db_manager.set_asset_price(asset.clone(), price)?,
+            None => error!("Error: failed to get price for asset {}", asset),
         }
-        let contract_function_result =
-            ScVal::from_xdr_base64(sim_result.results[0].xdr.clone(), Limits::none())?;
-        match &contract_function_result {
-            ScVal::Map(data_map) => {
-                if let Some(data_map) = data_map {
-                    let entry = &data_map[0].val;
-                    match entry {
-                        ScVal::I128(value) => {
-                            let mut price: i128 = value.into();
-                            price = price * SCALAR_7 / (10 as i128).pow(*oracle_decimals);
-                            db_manager.set_asset_price(asset.clone(), price)?;
-                        }
-                        _ => (),
-                    }
-                }
+    }
+    Ok(())
+}
+
+/// Returns the oracle's last price for `asset` in 7 decimals, and the timestamp it
+/// was reported at, or `None` if the oracle has no price for it.
+pub async fn get_last_price(
+    rpc: &RpcClient,
+    oracle_id: &String,
+    oracle_decimals: &u32,
+    asset: &String,
+) -> Result<Option<(i128, u64)>> {
+    // A random key is fine for simulation
+    let key = SigningKey::from_bytes(&[0; 32]);
+    let tx_builder = BlendTxBuilder {
+        contract_id: oracle_id.clone(),
+        signing_key: key.clone(),
+    };
+    let op = tx_builder.get_last_price(asset);
+    let transaction: TransactionEnvelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+        tx: Transaction {
+            source_account: MuxedAccount::Ed25519(Uint256(key.verifying_key().to_bytes())),
+            fee: 10000,
+            seq_num: stellar_xdr::curr::SequenceNumber(10),
+            cond: Preconditions::None,
+            memo: Memo::None,
+            operations: vec![op].try_into()?,
+            ext: stellar_xdr::curr::TransactionExt::V0,
+        },
+        signatures: VecM::default(),
+    });
+    let sim_result = rpc.simulate_transaction_envelope(&transaction).await?;
+    if sim_result.results.is_empty() {
+        return Ok(None);
+    }
+    let contract_function_result =
+        ScVal::from_xdr_base64(sim_result.results[0].xdr.clone(), Limits::none())?;
+    let ScVal::Map(Some(data_map)) = &contract_function_result else {
+        return Ok(None);
+    };
+    let mut price = None;
+    let mut timestamp = 0;
+    for entry in data_map.iter() {
+        match (&entry.key, &entry.val) {
+            (ScVal::Symbol(key), ScVal::I128(value)) if key.to_string() == "price" => {
+                let value: i128 = value.into();
+                price = Some(value * SCALAR_7 / 10_i128.pow(*oracle_decimals));
+            }
+            (ScVal::Symbol(key), ScVal::U64(value)) if key.to_string() == "timestamp" => {
+                timestamp = *value;
             }
             _ => (),
         }
     }
-    Ok(())
+    Ok(price.map(|price| (price, timestamp)))
 }
 
-pub async fn get_reserve_list(rpc: &Client, pool: &String) -> Result<Vec<String>> {
+pub async fn get_reserve_list(rpc: &RpcClient, pool: &String) -> Result<Vec<String>> {
     let mut assets: Vec<String> = Vec::new();
     let reserve_list_entry = rpc
         .get_ledger_entries(&[stellar_xdr::curr::LedgerKey::ContractData(
             LedgerKeyContractData {
-                contract: ScAddress::from_str(&pool)?,
+                contract: ScAddress::from_str(pool)?,
                 key: ScVal::Symbol(ScSymbol::from(ScSymbol::from(StringM::from_str(
                     "ResList",
                 )?))),
@@ -699,7 +751,7 @@ pub async fn get_reserve_list(rpc: &Client, pool: &String) -> Result<Vec<String>
 }
 
 pub async fn load_reserve_configs(
-    rpc: &Client,
+    rpc: &RpcClient,
     pool: &String,
     assets: &Vec<String>,
     db_manager: &DbManager,
@@ -707,7 +759,7 @@ pub async fn load_reserve_configs(
     let mut reserve_configs: HashMap<String, HashMap<String, ReserveConfig>> = HashMap::new();
     let mut ledger_keys: Vec<LedgerKey> = Vec::new();
     for asset in assets {
-        let asset_id = ScVal::Address(ScAddress::from_str(&asset)?);
+        let asset_id = ScVal::Address(ScAddress::from_str(asset)?);
 
         let reserve_config_key = ScVal::Vec(Some(ScVec::try_from(vec![
             ScVal::Symbol(ScSymbol::from(ScSymbol::from(StringM::from_str(
@@ -723,13 +775,13 @@ pub async fn load_reserve_configs(
         ])?));
         let reserve_config_ledger_key =
             stellar_xdr::curr::LedgerKey::ContractData(LedgerKeyContractData {
-                contract: ScAddress::from_str(&pool)?,
+                contract: ScAddress::from_str(pool)?,
                 key: reserve_config_key,
                 durability: stellar_xdr::curr::ContractDataDurability::Persistent,
             });
         let reserve_data_ledger_key =
             stellar_xdr::curr::LedgerKey::ContractData(LedgerKeyContractData {
-                contract: ScAddress::from_str(&pool)?,
+                contract: ScAddress::from_str(pool)?,
                 key: reserve_data_key,
                 durability: stellar_xdr::curr::ContractDataDurability::Persistent,
             });
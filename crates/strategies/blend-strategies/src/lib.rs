@@ -11,10 +11,20 @@
 pub mod auction_manager;
 /// This module contains the core strategy implementation.
 pub mod auctioneer_strategy;
+/// This module contains a backfill of pool users from historical events.
+pub mod backfill;
 pub mod constants;
 pub mod db_manager;
 pub mod file_logger;
 pub mod helper;
 pub mod liquidation_strategy;
+/// This module contains a collector that discovers new pools.
+pub mod pool_collector;
+/// This module contains a collector that reports oracle price changes.
+pub mod price_collector;
 pub mod transaction_builder;
+/// This module contains a store of every attempt at submitting a transaction.
+pub mod tx_store;
 pub mod types;
+/// This module contains a collector that tracks our token balances.
+pub mod wallet_collector;
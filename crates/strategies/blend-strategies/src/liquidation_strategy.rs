@@ -5,15 +5,24 @@ use crate::{
     file_logger::log_error,
     helper::{
         bstop_token_to_usdc, decode_auction_data, decode_scaddress_to_string, get_balance,
-        get_pool_positions, send_slack_message, validate_assets,
+        get_pool_positions, get_reserve_list, get_token_name, load_reserve_configs,
+        positions_ledger_key, send_slack_message, user_positions_from_ledger_entry,
+        validate_assets,
     },
     transaction_builder::BlendTxBuilder,
-    types::{Action, Config, Event, UserPositions},
+    types::{Action, Config, Event, EventInterest, UserPositions},
 };
 use anyhow::Result;
 use artemis_core::{
-    collectors::block_collector::NewBlock, executors::soroban_executor::GasBidInfo,
-    executors::soroban_executor::SubmitStellarTx, types::Strategy,
+    collectors::account_collector::AccountEvent,
+    collectors::block_collector::NewBlock,
+    collectors::execution_result_collector::{ExecutionResult, ExecutionStatus},
+    collectors::ledger_entry_collector::{LedgerEntryChange, WatchedKeys},
+    delivery::Interest,
+    executors::soroban_executor::GasBidInfo,
+    executors::soroban_executor::SubmitStellarTx,
+    types::Strategy,
+    utilities::rpc::RpcClient,
 };
 use async_trait::async_trait;
 use core::panic;
@@ -25,16 +34,24 @@ use std::{
     time::Duration,
     {collections::HashMap, str::FromStr, vec},
 };
-use stellar_rpc_client::{Client, Event as SorobanEvent};
+use stellar_rpc_client::Event as SorobanEvent;
 use stellar_xdr::curr::{
     AccountId, LedgerEntryData, LedgerKeyContractData, Limits, PublicKey, ReadXdr, ScAddress,
     ScMap, ScMapEntry, ScSpecTypeDef, ScSymbol, ScVal, ScVec, StringM, Uint256, VecM,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// Ledgers a fill that landed waits for its fill event before it can be
+/// submitted again.
+const FILL_EVENT_LEDGERS: u32 = 10;
+
+/// Ledgers without a balance update after which our wallet is read in full, in
+/// case the balance updates stopped.
+const WALLET_STALE_LEDGERS: u32 = 60;
 
 pub struct BlendLiquidator {
     /// Soroban RPC client for interacting with chain
-    rpc: Client,
+    rpc: RpcClient,
     /// The path to the db directory
     db_manager: DbManager,
     /// The slack api key
@@ -45,6 +62,8 @@ pub struct BlendLiquidator {
     supported_liabilities: Vec<String>,
     /// Vec of Blend pool addresses to bid on auctions in
     pools: Vec<String>,
+    /// The events routed to us, which adopted pools are added to
+    event_interest: EventInterest,
     /// Backstop ID
     backstop_id: String,
     /// Amount of profits to bid in gas
@@ -55,7 +74,7 @@ pub struct BlendLiquidator {
     pending_fill: Vec<OngoingAuction>,
     /// Our positions
     bankroll: HashMap<String, UserPositions>,
-    /// Our wallet
+    /// Our wallet, in balances we can spend
     wallet: HashMap<String, i128>,
     /// Our signing address
     us: SigningKey,
@@ -69,24 +88,39 @@ pub struct BlendLiquidator {
     usdc_address: String,
     // XLM address
     xlm_address: String,
+    /// The balance of every asset held back from fills to pay transaction fees with
+    fee_reserves: HashMap<String, i128>,
     // Whether or not we will force fill liquidations
     force_fill: bool,
+    /// Ledger entries to keep our positions current through, if any
+    watched_keys: Option<WatchedKeys>,
+    /// Whether balance updates keep our wallet current
+    wallet_updates: bool,
+    /// The latest ledger we saw
+    latest_ledger: u32,
+    /// The ledger our wallet was last known current at
+    wallet_ledger: u32,
+    /// Whether account events keep the classic balances in our wallet current
+    account_events: bool,
+    /// The stellar asset contract of every wallet token that wraps a classic
+    /// asset, keyed by asset name
+    classic_assets: HashMap<String, String>,
 }
 
 impl BlendLiquidator {
-    pub async fn new(config: &Config, signing_key: &SigningKey) -> Result<Self> {
-        let client = Client::new(config.rpc_url.as_str())?;
+    pub async fn new(rpc: RpcClient, config: &Config, signing_key: &SigningKey) -> Result<Self> {
         let db_manager = DbManager::new(config.db_path.clone());
         let mut assets = config.supported_collateral.clone();
         assets.extend(config.supported_liabilities.clone());
         db_manager.initialize(&assets)?;
         Ok(Self {
-            rpc: client,
+            rpc,
             db_manager,
             slack_api_url_key: config.slack_api_url_key.clone(),
             supported_collateral: config.supported_collateral.clone(),
             supported_liabilities: config.supported_liabilities.clone(),
             pools: config.pools.clone(),
+            event_interest: EventInterest::new(),
             backstop_id: config.backstop.clone(),
             bid_percentage: config.bid_percentage,
             required_profit_pct: config.required_profit_pct,
@@ -102,29 +136,72 @@ impl BlendLiquidator {
             backstop_token_address: config.backstop_token_address.clone(),
             usdc_address: config.usdc_token_address.clone(),
             xlm_address: config.xlm_address.clone(),
+            fee_reserves: HashMap::from([(config.xlm_address.clone(), 100 * SCALAR_7)]),
             force_fill: config.force_fill,
+            watched_keys: None,
+            wallet_updates: false,
+            latest_ledger: 0,
+            wallet_ledger: 0,
+            account_events: false,
+            classic_assets: HashMap::new(),
         })
     }
+
+    /// Keeps our wallet current from balance update events instead of reading
+    /// every balance before assessing a fill. The wallet is still read in full
+    /// on every sync, and when no balance update arrived for
+    /// `WALLET_STALE_LEDGERS`.
+    pub fn with_wallet_updates(mut self) -> Self {
+        self.wallet_updates = true;
+        self
+    }
+
+    /// Keeps the wallet balances of tokens wrapping a classic asset current from
+    /// account events.
+    pub fn with_account_events(mut self) -> Self {
+        self.account_events = true;
+        self
+    }
+
+    /// Fees are paid by a separate fee account, so all of our spendable XLM can be
+    /// bid in fills.
+    pub fn with_fee_account(mut self) -> Self {
+        self.fee_reserves.clear();
+        self
+    }
+
+    /// Watches our positions in every pool through `watched_keys`, so changes we
+    /// didn't make ourselves are picked up too.
+    pub fn with_watched_keys(mut self, watched_keys: WatchedKeys) -> Self {
+        self.watched_keys = Some(watched_keys);
+        self
+    }
 }
 
 #[async_trait]
 impl Strategy<Event, Action> for BlendLiquidator {
     async fn sync_state(&mut self) -> Result<()> {
+        // rebuild pending fills from chain so a resync doesn't track auctions twice
+        let previous = std::mem::take(&mut self.pending_fill);
         self.sync_liquidator(None).await?;
+        if self.account_events && self.classic_assets.is_empty() {
+            self.load_classic_assets().await;
+        }
         for pool in self.pools.clone() {
-            // Get ongoing interest auctions
-            self.get_interest_auction(pool.clone()).await?;
-            // Get ongoing bad debt auctions
-            self.get_bad_debt_auction(pool.clone()).await?;
+            self.watch_positions(&pool)?;
+            self.sync_pool_auctions(&pool).await?;
         }
-        // Get all liquidations ongoing
-        let users = self.db_manager.get_users()?;
-        for user in users {
-            for pool in self.pools.clone() {
-                match self.get_user_liquidation(pool.clone(), user.clone()).await {
-                    Ok(_) => (),
-                    Err(e) => error!("Failed to get {} liquidation: {}", user, e),
-                }
+        // auctions that still exist keep their in-flight fill, so it isn't submitted twice
+        for pending in self.pending_fill.iter_mut() {
+            if let Some(old) = previous.iter().find(|old| {
+                old.pool == pending.pool
+                    && old.user == pending.user
+                    && old.auction_type == pending.auction_type
+            }) {
+                pending.target_block = old.target_block;
+                pending.block_submitted = old.block_submitted;
+                pending.submitted_action_id = old.submitted_action_id.clone();
+                pending.last_fill_ledger = old.last_fill_ledger;
             }
         }
 
@@ -175,10 +252,109 @@ impl Strategy<Event, Action> for BlendLiquidator {
                         }
                     }
                 }
+                Event::ExecutionResult(ref result) => {
+                    return self.process_execution_result(*result.clone()).await;
+                }
+                // keep our own prices so fills don't depend on the auctioneer storing them
+                Event::PriceUpdate(ref update) => {
+                    if let Err(e) = self
+                        .db_manager
+                        .set_asset_price(update.asset.clone(), update.price)
+                    {
+                        error!("Error storing price for asset {}: {}", update.asset, e);
+                    }
+                    return Vec::new();
+                }
+                Event::PoolDiscovered(ref discovered) => {
+                    if let Err(e) = self.adopt_pool(&discovered.pool).await {
+                        error!("Error syncing discovered pool {}: {}", discovered.pool, e);
+                    }
+                    return Vec::new();
+                }
+                Event::BalanceUpdate(ref update) => {
+                    self.wallet.insert(update.token.clone(), update.balance);
+                    self.wallet_ledger = self.latest_ledger;
+                    return Vec::new();
+                }
+                Event::AccountEvent(ref account_event) => {
+                    if let AccountEvent::Balance {
+                        asset, spendable, ..
+                    } = account_event.as_ref()
+                    {
+                        if let Some(token) = self.classic_assets.get(asset) {
+                            self.wallet.insert(token.clone(), *spendable);
+                        }
+                    }
+                    return Vec::new();
+                }
+                Event::LedgerEntryChange(ref change) => {
+                    if let Err(e) = self.process_ledger_entry_change(change) {
+                        error!("Error processing ledger entry change: {}", e);
+                    }
+                    return Vec::new();
+                }
+                Event::EventGap(ref gap) => {
+                    info!(
+                        "Liquidator missed events from ledger {} to {}, resyncing",
+                        gap.from_ledger, gap.to_ledger
+                    );
+                    if let Err(e) = self.sync_state().await {
+                        error!("Error resyncing after event gap: {}", e);
+                    }
+                    return Vec::new();
+                }
             }
         }
         return Vec::new();
     }
+
+    fn interest(&self) -> Interest<Event> {
+        self.event_interest
+            .clone()
+            .with_new_blocks()
+            .with_execution_results()
+            .with_price_updates()
+            .with_pool_discovery()
+            .with_ledger_entries()
+            .with_balance_updates()
+            .with_account_events()
+            .with_soroban_events(
+                &self.pools,
+                &[
+                    "new_liquidation_auction",
+                    "delete_liquidation_auction",
+                    "new_auction",
+                    "fill_auction",
+                ],
+            )
+            .into()
+    }
+
+    async fn on_lag(&mut self, skipped: u64) -> Result<()> {
+        // a skipped fill_auction or new_auction event leaves our pending fills stale
+        info!("Liquidator missed {} events, resyncing", skipped);
+        self.sync_state().await
+    }
+
+    async fn on_shutdown(&mut self) -> Result<()> {
+        // pending fills are rebuilt from chain on the next sync, so we only report what was dropped
+        let msg = format!(
+            "Liquidator: {} shutting down with {} pending fills: {:?}",
+            self.us_public,
+            self.pending_fill.len(),
+            self.pending_fill
+                .iter()
+                .map(|pending| (
+                    pending.pool.clone(),
+                    pending.user.clone(),
+                    pending.target_block
+                ))
+                .collect::<Vec<_>>()
+        );
+        info!("{}", msg.clone());
+        send_slack_message(&self.slack_api_url_key, &msg).await?;
+        Ok(())
+    }
 }
 
 impl BlendLiquidator {
@@ -429,7 +605,11 @@ impl BlendLiquidator {
                         if fill_percentage == 100 {
                             self.pending_fill.remove(index);
                         } else {
+                            // the rest of the auction can be filled right away
+                            let pending_fill = &mut self.pending_fill[index];
                             pending_fill.partial_fill_update(fill_percentage as u64);
+                            pending_fill.block_submitted = 0;
+                            pending_fill.last_fill_ledger = event.ledger;
                         }
                         break;
                     }
@@ -443,6 +623,20 @@ impl BlendLiquidator {
     /// Process new block events, updating the internal state.
     async fn process_new_block_event(&mut self, event: NewBlock) -> Result<Vec<Action>> {
         let mut actions = vec![];
+        self.latest_ledger = event.number;
+        // the wallet was read on startup, before we saw a ledger
+        if self.wallet_ledger == 0 {
+            self.wallet_ledger = event.number;
+        }
+        if self.wallet_updates
+            && event.number.saturating_sub(self.wallet_ledger) > WALLET_STALE_LEDGERS
+        {
+            warn!(
+                "No balance updates for {} ledgers, reading our wallet",
+                WALLET_STALE_LEDGERS
+            );
+            self.sync_wallet().await?;
+        }
         let liquidator_id = self.us_public.clone();
         let mut pending_fills = self.pending_fill.clone();
         for pending in pending_fills.iter_mut() {
@@ -494,6 +688,7 @@ impl BlendLiquidator {
                 };
                 if self.assess_fill(event.number, pending) {
                     pending.block_submitted = event.number + 2;
+                    pending.submitted_action_id = Some(pending.action_id(event.number));
                     let op_builder = BlendTxBuilder {
                         contract_id: pending.pool.clone(),
                         signing_key: self.us.clone(),
@@ -507,7 +702,7 @@ impl BlendLiquidator {
                         &self.supported_collateral,
                         &self.min_hf,
                         event.number + 1,
-                        &self.xlm_address,
+                        &self.fee_reserves,
                     )?;
                     info!(
                         "Sending auction fill to executor for user: {:?} with requests: {:?}",
@@ -517,6 +712,7 @@ impl BlendLiquidator {
                     let op =
                         op_builder.submit(&liquidator_id, &liquidator_id, &liquidator_id, requests);
                     actions.push(Action::SubmitTx(SubmitStellarTx {
+                        action_id: pending.action_id(event.number),
                         op,
                         gas_bid_info: Some(GasBidInfo {
                             total_profit: profit
@@ -533,6 +729,8 @@ impl BlendLiquidator {
                         }),
                         signing_key: self.us.clone(),
                         max_retries: 0,
+                        // the fill is priced for the next ledger and resubmitted after this one
+                        target_ledger: Some(event.number + 2),
                     }));
                 }
             }
@@ -541,11 +739,159 @@ impl BlendLiquidator {
         return Ok(actions);
     }
 
+    /// Process the result of a fill we submitted. A fill that landed isn't
+    /// submitted again until its fill event updates it, or for
+    /// `FILL_EVENT_LEDGERS`. Failed fills are retried on the next block unless the
+    /// auction is gone, in which case the fill is dropped. Results of any but the
+    /// latest fill submitted for an auction are ignored.
+    async fn process_execution_result(&mut self, result: ExecutionResult) -> Vec<Action> {
+        let index = match self.pending_fill.iter().position(|pending| {
+            pending.submitted_action_id.as_deref() == Some(result.action_id.as_str())
+        }) {
+            Some(index) => index,
+            None => return vec![],
+        };
+        if result.status == ExecutionStatus::Success {
+            info!(
+                "Fill {} included in ledger {:?} with fee {:?}",
+                result.action_id, result.ledger, result.fee_charged
+            );
+            // hold the fill back unless its fill event was already processed
+            let pending = &mut self.pending_fill[index];
+            let ledger = result.ledger.unwrap_or(0);
+            if ledger > pending.last_fill_ledger {
+                pending.block_submitted = pending.block_submitted.max(ledger + FILL_EVENT_LEDGERS);
+            }
+            return vec![];
+        }
+
+        let pending = &self.pending_fill[index];
+        // if the auction can't be checked, assume it's still open and retry
+        let exists = self
+            .auction_exists(&pending.pool, &pending.user, pending.auction_type)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Error checking auction {}: {}", result.action_id, e);
+                true
+            });
+        if exists {
+            info!(
+                "Fill {} failed with error: {:?}, retrying next block",
+                result.action_id, result.error
+            );
+            self.pending_fill[index].block_submitted = 0;
+        } else {
+            let msg = format!(
+                "Liquidator: {} lost auction {} with error: {:?}",
+                self.us_public, result.action_id, result.error
+            );
+            info!("{}", msg.clone());
+            self.pending_fill.remove(index);
+            if let Err(e) = send_slack_message(&self.slack_api_url_key, &msg).await {
+                error!("Error sending slack message: {}", e);
+            }
+        }
+        vec![]
+    }
+
+    /// Check whether an auction is still open on chain.
+    async fn auction_exists(&self, pool: &str, user: &str, auction_type: u32) -> Result<bool> {
+        let auction_key = ScVal::Vec(Some(ScVec::try_from(vec![
+            ScVal::Symbol(ScSymbol::from(StringM::from_str("Auction")?)),
+            ScVal::Map(Some(ScMap(VecM::try_from(vec![
+                ScMapEntry {
+                    key: from_string_primitive("auct_type", &ScSpecTypeDef::Symbol)?,
+                    val: ScVal::U32(auction_type),
+                },
+                ScMapEntry {
+                    key: from_string_primitive("user", &ScSpecTypeDef::Symbol)?,
+                    val: ScVal::Address(ScAddress::from_str(user)?),
+                },
+            ])?))),
+        ])?));
+        let ledger_key = stellar_xdr::curr::LedgerKey::ContractData(LedgerKeyContractData {
+            contract: ScAddress::from_str(pool)?,
+            key: auction_key,
+            durability: stellar_xdr::curr::ContractDataDurability::Temporary,
+        });
+        let result = self.rpc.get_ledger_entries(&[ledger_key]).await?;
+        Ok(result.entries.is_some_and(|entries| !entries.is_empty()))
+    }
+
+    /// Loads a pool's ongoing interest, bad debt, and liquidation auctions.
+    async fn sync_pool_auctions(&mut self, pool: &str) -> Result<()> {
+        // Get ongoing interest auctions
+        self.get_interest_auction(pool.to_string()).await?;
+        // Get ongoing bad debt auctions
+        self.get_bad_debt_auction(pool.to_string()).await?;
+        // Get all liquidations ongoing
+        let users = self.db_manager.get_users()?;
+        for user in users {
+            match self
+                .get_user_liquidation(pool.to_string(), user.clone())
+                .await
+            {
+                Ok(_) => (),
+                Err(e) => error!("Failed to get {} liquidation: {}", user, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts tracking a discovered pool. The pool is kept even if syncing it
+    /// fails, so the next resync picks it up.
+    async fn adopt_pool(&mut self, pool: &String) -> Result<()> {
+        if self.pools.contains(pool) {
+            return Ok(());
+        }
+        info!("Liquidator adopting pool {}", pool);
+        self.pools.push(pool.clone());
+        // route the pool's events to us before syncing, so the sync covers any filtered out before
+        self.event_interest.add_contract_id(pool);
+        self.watch_positions(pool)?;
+        let assets = get_reserve_list(&self.rpc, pool).await?;
+        load_reserve_configs(&self.rpc, pool, &assets, &self.db_manager).await?;
+        self.sync_liquidator(Some(pool.clone())).await?;
+        self.sync_pool_auctions(pool).await
+    }
+
+    /// Watches our positions in a pool, if we watch ledger entries.
+    fn watch_positions(&self, pool: &str) -> Result<()> {
+        if let Some(watched_keys) = &self.watched_keys {
+            watched_keys.watch(positions_ledger_key(pool, &self.us_public)?);
+        }
+        Ok(())
+    }
+
+    /// Updates our positions in a pool when its entry changes.
+    fn process_ledger_entry_change(&mut self, change: &LedgerEntryChange) -> Result<()> {
+        for pool in self.pools.clone() {
+            if positions_ledger_key(&pool, &self.us_public)? != change.key {
+                continue;
+            }
+            info!("Our positions in pool {} changed", pool);
+            match &change.new_value {
+                Some(value) => {
+                    let positions =
+                        user_positions_from_ledger_entry(value, &pool, &self.db_manager)?;
+                    self.bankroll.insert(pool, positions);
+                }
+                None => {
+                    self.bankroll.remove(&pool);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Sync the liquidator state with the chain.
     ///
     /// # Arguments
-    /// - `pool`: Option<String> - The pool to sync. If None, sync all pools.
+    /// - `pool`: Option<String> - The pool to sync. If None, sync all pools. The
+    ///   wallet is only synced with a pool if no balance updates keep it current.
     async fn sync_liquidator(&mut self, pool: Option<String>) -> Result<()> {
+        // balance updates keep the wallet current between full syncs
+        let pool_is_some = pool.is_some();
         // Update pool positions for pool_id
         if pool.is_some() {
             let pool = pool.unwrap();
@@ -568,6 +914,14 @@ impl BlendLiquidator {
             }
         }
 
+        if pool_is_some && self.wallet_updates {
+            return Ok(());
+        }
+        self.sync_wallet().await
+    }
+
+    /// Reads our balance of the backstop token and every supported liability.
+    async fn sync_wallet(&mut self) -> Result<()> {
         // Update wallet balance for backstop token
         match get_balance(
             &self.rpc,
@@ -595,9 +949,26 @@ impl BlendLiquidator {
                 }
             }
         }
+        self.wallet_ledger = self.latest_ledger;
         Ok(())
     }
 
+    /// Maps the asset every wallet token wraps to the token, for the tokens that
+    /// are stellar asset contracts. These are named after their asset.
+    async fn load_classic_assets(&mut self) {
+        let mut tokens = self.supported_liabilities.clone();
+        tokens.push(self.backstop_token_address.clone());
+        for token in tokens {
+            match get_token_name(&self.rpc, &token).await {
+                Ok(name) if name == "native" || name.contains(':') => {
+                    self.classic_assets.insert(name, token);
+                }
+                Ok(_) => (),
+                Err(e) => error!("Error reading name of token {}: {}", token, e),
+            }
+        }
+    }
+
     async fn get_user_liquidation(&mut self, pool: String, user: String) -> Result<()> {
         let pool_id = ScAddress::from_str(&pool)?;
         let reserve_data_key = ScVal::Vec(Some(ScVec::try_from(vec![
@@ -621,10 +992,7 @@ impl BlendLiquidator {
                 key: reserve_data_key,
                 durability: stellar_xdr::curr::ContractDataDurability::Temporary,
             });
-        let result = self
-            .rpc
-            .get_ledger_entries(&vec![position_ledger_key])
-            .await?;
+        let result = self.rpc.get_ledger_entries(&[position_ledger_key]).await?;
         if let Some(entries) = result.entries {
             for entry in entries {
                 let value: LedgerEntryData =
@@ -693,10 +1061,7 @@ impl BlendLiquidator {
                 key: reserve_data_key,
                 durability: stellar_xdr::curr::ContractDataDurability::Temporary,
             });
-        let result = self
-            .rpc
-            .get_ledger_entries(&vec![position_ledger_key])
-            .await?;
+        let result = self.rpc.get_ledger_entries(&[position_ledger_key]).await?;
         if let Some(entries) = result.entries {
             for entry in entries {
                 let value: LedgerEntryData =
@@ -778,10 +1143,7 @@ impl BlendLiquidator {
                 key: data_key,
                 durability: stellar_xdr::curr::ContractDataDurability::Temporary,
             });
-        let result = self
-            .rpc
-            .get_ledger_entries(&vec![auction_ledger_key])
-            .await?;
+        let result = self.rpc.get_ledger_entries(&[auction_ledger_key]).await?;
         if let Some(entries) = result.entries {
             for entry in entries {
                 let value: LedgerEntryData =
@@ -0,0 +1,233 @@
+use std::path::Path;
+
+use anyhow::Result;
+use artemis_core::{
+    collectors::log_collector::{EventFilter, LogCollector, LogEvent},
+    types::{Collector, CollectorStream},
+    utilities::rpc::RpcClient,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use stellar_rpc_client::{Event as SorobanEvent, EventType};
+use stellar_xdr::curr::{Limits, ReadXdr, ScVal};
+use tokio::sync::watch;
+use tokio_stream::StreamExt;
+use tracing::{error, info, warn};
+
+use crate::db_manager::DbManager;
+
+/// Where a [PoolDiscovered](PoolDiscovered) pool was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolSource {
+    /// The pool factory deployed the pool.
+    Factory,
+    /// The backstop added the pool to the reward zone.
+    RewardZone,
+}
+
+/// Which discovered pools get adopted. A pool is adopted if the allow-list is
+/// empty or contains it, and the deny-list doesn't.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct PoolFilter {
+    #[serde(default)]
+    pub allow_list: Vec<String>,
+    #[serde(default)]
+    pub deny_list: Vec<String>,
+}
+
+impl PoolFilter {
+    pub fn adopts(&self, pool: &str) -> bool {
+        let listed = |list: &[String]| list.iter().any(|listed| listed == pool);
+        (self.allow_list.is_empty() || listed(&self.allow_list)) && !listed(&self.deny_list)
+    }
+}
+
+/// A pool that was adopted while the bot was running.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolDiscovered {
+    pub pool: String,
+    pub source: PoolSource,
+    pub ledger: u32,
+}
+
+/// A collector that watches the pool factory's deploy events and the backstop's
+/// reward zone changes, and emits a [PoolDiscovered](PoolDiscovered) for every
+/// pool its [filter](PoolFilter) adopts. Every pool is adopted at most once.
+pub struct PoolCollector {
+    logs: LogCollector,
+    factory: String,
+    backstop: String,
+    /// Every adopted pool, starting with the already known ones.
+    pools: watch::Sender<Vec<String>>,
+    filter: PoolFilter,
+    /// Where adopted pools are recorded, if anywhere.
+    db_manager: Option<DbManager>,
+}
+
+impl PoolCollector {
+    /// Creates a collector that adopts new pools on top of the already known
+    /// `pools`.
+    pub fn new(rpc: RpcClient, factory: String, backstop: String, pools: Vec<String>) -> Self {
+        let filter = EventFilter {
+            event_type: EventType::Contract,
+            contract_ids: vec![factory.clone(), backstop.clone()],
+            topics: vec![],
+        };
+        Self {
+            logs: LogCollector::new(rpc, filter),
+            factory,
+            backstop,
+            pools: watch::channel(pools).0,
+            filter: PoolFilter::default(),
+            db_manager: None,
+        }
+    }
+
+    /// Sets which pools get adopted. Every pool is adopted by default.
+    pub fn with_filter(mut self, filter: PoolFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Records adopted pools in the db at `db_path`, and persists the cursor next
+    /// to it so pools deployed while the bot was down are still discovered.
+    pub fn with_db_path(mut self, db_path: &str) -> Self {
+        self.logs = self
+            .logs
+            .with_cursor_path(Path::new(db_path).join("pool_cursor.json"));
+        self.db_manager = Some(DbManager::new(db_path.to_string()));
+        self
+    }
+
+    /// Returns a receiver that always holds every adopted pool, including the
+    /// ones the collector was created with.
+    pub fn pools(&self) -> watch::Receiver<Vec<String>> {
+        self.pools.subscribe()
+    }
+}
+
+/// Returns the pool a factory or backstop event adds, if it adds one.
+fn discovered_pool(
+    event: &SorobanEvent,
+    factory: &str,
+    backstop: &str,
+) -> Option<(String, PoolSource)> {
+    let ScVal::Symbol(name) = ScVal::from_xdr_base64(event.topic.first()?, Limits::none()).ok()?
+    else {
+        return None;
+    };
+    let source = match name.to_utf8_string_lossy().as_str() {
+        "deploy" if event.contract_id == factory => PoolSource::Factory,
+        "rw_zone" | "rw_zone_add" if event.contract_id == backstop => PoolSource::RewardZone,
+        _ => return None,
+    };
+    // a deploy emits the pool, a reward zone change emits the pool added first
+    let pool = match ScVal::from_xdr_base64(&event.value, Limits::none()).ok()? {
+        ScVal::Vec(Some(values)) => values.first()?.clone(),
+        value => value,
+    };
+    match pool {
+        ScVal::Address(pool) => Some((pool.to_string(), source)),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl Collector<PoolDiscovered> for PoolCollector {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, PoolDiscovered>> {
+        let stream = self.logs.get_event_stream().await?;
+        let factory = &self.factory;
+        let backstop = &self.backstop;
+        let pools = &self.pools;
+        let filter = &self.filter;
+        let db_manager = &self.db_manager;
+        let stream = stream.filter_map(move |event| {
+            let event = match event {
+                Ok(LogEvent::Event(event)) => event,
+                Ok(LogEvent::Gap(gap)) => {
+                    warn!("pool discovery skipped {:?}, pools may be missed", gap);
+                    return None;
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            let (pool, source) = discovered_pool(&event, factory, backstop)?;
+            if pools.borrow().contains(&pool) {
+                return None;
+            }
+            if !filter.adopts(&pool) {
+                info!("ignoring filtered pool {}", pool);
+                return None;
+            }
+            info!("adopting pool {} from {:?}", pool, source);
+            if let Some(db_manager) = db_manager {
+                if let Err(e) = db_manager.add_discovered_pool(&pool) {
+                    error!("Error recording discovered pool {}: {}", pool, e);
+                }
+            }
+            pools.send_modify(|pools| pools.push(pool.clone()));
+            Some(Ok(PoolDiscovered {
+                pool,
+                source,
+                ledger: event.ledger,
+            }))
+        });
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{discovered_pool, PoolFilter, PoolSource};
+    use stellar_rpc_client::Event;
+    use stellar_xdr::curr::{Hash, Limits, ScAddress, ScSymbol, ScVal, ScVec, WriteXdr};
+
+    fn event(contract_id: &str, name: &str, value: ScVal) -> Event {
+        let name = ScVal::Symbol(ScSymbol(name.try_into().unwrap()));
+        Event {
+            event_type: "contract".to_string(),
+            ledger: 1,
+            ledger_closed_at: String::new(),
+            id: String::new(),
+            paging_token: String::new(),
+            contract_id: contract_id.to_string(),
+            topic: vec![name.to_xdr_base64(Limits::none()).unwrap()],
+            value: value.to_xdr_base64(Limits::none()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_discovered_pool() {
+        let pool = ScVal::Address(ScAddress::Contract(Hash([1; 32])));
+        let removed = ScVal::Address(ScAddress::Contract(Hash([2; 32])));
+        let expected = ScAddress::Contract(Hash([1; 32])).to_string();
+
+        let deploy = event("factory", "deploy", pool.clone());
+        assert_eq!(
+            discovered_pool(&deploy, "factory", "backstop"),
+            Some((expected.clone(), PoolSource::Factory))
+        );
+        let zone = ScVal::Vec(Some(ScVec(vec![pool.clone(), removed].try_into().unwrap())));
+        let rw_zone = event("backstop", "rw_zone", zone);
+        assert_eq!(
+            discovered_pool(&rw_zone, "factory", "backstop"),
+            Some((expected, PoolSource::RewardZone))
+        );
+        // a deploy event from another contract is not the factory's
+        let spoofed = event("backstop", "deploy", pool);
+        assert_eq!(discovered_pool(&spoofed, "factory", "backstop"), None);
+    }
+
+    #[test]
+    fn test_pool_filter() {
+        let list = |pools: &[&str]| pools.iter().map(|pool| pool.to_string()).collect();
+        assert!(PoolFilter::default().adopts("a"));
+        let filter = PoolFilter {
+            allow_list: list(&["a", "b"]),
+            deny_list: list(&["b"]),
+        };
+        assert!(filter.adopts("a"));
+        assert!(!filter.adopts("b"));
+        assert!(!filter.adopts("c"));
+    }
+}
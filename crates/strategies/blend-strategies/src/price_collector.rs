@@ -0,0 +1,154 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use artemis_core::{
+    types::{Collector, CollectorStream},
+    utilities::{backoff::Backoff, rpc::RpcClient},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::warn;
+
+use crate::helper::get_last_price;
+
+/// A new oracle price for an asset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceUpdate {
+    pub asset: String,
+    /// The price in 7 decimals.
+    pub price: i128,
+    /// When the oracle reported the price, in seconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+/// A collector that polls the oracle's `lastprice` for a set of assets and emits a
+/// [PriceUpdate](PriceUpdate) whenever one of them changes. Every price is emitted
+/// on the first poll.
+pub struct PriceCollector {
+    rpc: RpcClient,
+    oracle_id: String,
+    oracle_decimals: u32,
+    assets: Vec<String>,
+    poll_interval: Duration,
+    /// The most consecutive failed polls retried before the stream fails.
+    max_retries: u32,
+    backoff: Backoff,
+    last_prices: HashMap<String, i128>,
+}
+
+impl PriceCollector {
+    pub fn new(
+        rpc: RpcClient,
+        oracle_id: String,
+        oracle_decimals: u32,
+        assets: Vec<String>,
+    ) -> Self {
+        Self {
+            rpc,
+            oracle_id,
+            oracle_decimals,
+            assets,
+            poll_interval: Duration::from_secs(10),
+            max_retries: 5,
+            backoff: Backoff::default(),
+            last_prices: HashMap::new(),
+        }
+    }
+
+    /// Sets how often the oracle is polled.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Retries up to `max_retries` consecutive failed polls, waiting `backoff`
+    /// between them, before failing the stream.
+    pub fn with_retries(mut self, max_retries: u32, backoff: Backoff) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+}
+
+/// Fetches the price and its timestamp of every asset the oracle has a price for.
+async fn fetch_prices(
+    rpc: &RpcClient,
+    oracle_id: &str,
+    oracle_decimals: u32,
+    assets: &[String],
+) -> Result<Vec<(String, i128, u64)>> {
+    let oracle_id = oracle_id.to_string();
+    let mut prices = vec![];
+    for asset in assets {
+        match get_last_price(rpc, &oracle_id, &oracle_decimals, asset).await? {
+            Some((price, timestamp)) => prices.push((asset.clone(), price, timestamp)),
+            None => warn!("oracle has no price for asset {}", asset),
+        }
+    }
+    Ok(prices)
+}
+
+#[async_trait]
+impl Collector<PriceUpdate> for PriceCollector {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, PriceUpdate>> {
+        let (sender, receiver) = mpsc::channel(512);
+        let rpc = self.rpc.clone();
+        let oracle_id = self.oracle_id.clone();
+        let oracle_decimals = self.oracle_decimals;
+        let assets = self.assets.clone();
+        let mut last_prices = self.last_prices.clone();
+        let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let mut poll = interval(self.poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        tokio::spawn(async move {
+            let mut attempt = 0;
+            while !sender.is_closed() {
+                poll.tick().await;
+                let prices = match fetch_prices(&rpc, &oracle_id, oracle_decimals, &assets).await {
+                    Ok(prices) => {
+                        attempt = 0;
+                        prices
+                    }
+                    Err(e) if attempt < max_retries => {
+                        let delay = backoff.delay(attempt);
+                        warn!("error fetching prices: {}, retrying in {:?}", e, delay);
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e)).await;
+                        return;
+                    }
+                };
+                for (asset, price, timestamp) in prices {
+                    if last_prices.get(&asset) == Some(&price) {
+                        continue;
+                    }
+                    last_prices.insert(asset.clone(), price);
+                    let update = PriceUpdate {
+                        asset,
+                        price,
+                        timestamp,
+                    };
+                    if sender.send(Ok(update)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        // remember the prices so a restarted stream only emits changes
+        let last_prices = &mut self.last_prices;
+        let stream = ReceiverStream::new(receiver).map(move |update: Result<PriceUpdate>| {
+            if let Ok(update) = &update {
+                last_prices.insert(update.asset.clone(), update.price);
+            }
+            update
+        });
+        Ok(Box::pin(stream))
+    }
+}
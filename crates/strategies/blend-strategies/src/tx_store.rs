@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use artemis_core::{
+    collectors::execution_result_collector::ExecutionStatus,
+    executors::attempts::{AttemptStore, TxAttempt},
+};
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+
+const ATTEMPT_COLUMNS: &str = "id, action_id, pool, user, auction_type, attempt, submitted_at, tx_hash, status, ledger, fee_bid, fee_charged, result_xdr, result, events, error";
+
+/// A recorded [TxAttempt](TxAttempt), along with the auction its action acted on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredAttempt {
+    pub id: i64,
+    pub pool: Option<String>,
+    pub user: Option<String>,
+    pub auction_type: Option<u32>,
+    pub attempt: TxAttempt,
+}
+
+/// Keeps every attempt at submitting a transaction in `transactions.db`, one row
+/// per attempt, and answers what happened to a submission.
+#[derive(Debug, Clone)]
+pub struct TxStore {
+    pub path: PathBuf,
+}
+
+impl TxStore {
+    /// Opens the store in the db directory at `db_path`, creating it if needed.
+    pub fn new(db_path: &str) -> Result<Self> {
+        let store = Self {
+            path: Path::new(db_path).join("transactions.db"),
+        };
+        let db = Connection::open(&store.path)?;
+        db.execute(
+            "create table if not exists tx_attempts (
+                id integer primary key,
+                action_id string not null,
+                pool string,
+                user string,
+                auction_type integer,
+                attempt integer not null,
+                submitted_at integer not null,
+                tx_hash string,
+                status string not null,
+                ledger integer,
+                fee_bid integer,
+                fee_charged integer,
+                result_xdr string,
+                result string,
+                events string not null,
+                error string
+             )",
+            [],
+        )?;
+        db.execute(
+            "create index if not exists tx_attempts_action on tx_attempts (action_id)",
+            [],
+        )?;
+        db.execute(
+            "create index if not exists tx_attempts_auction on tx_attempts (pool, user)",
+            [],
+        )?;
+        Ok(store)
+    }
+
+    /// Returns every attempt at submitting the action `action_id`, oldest first.
+    pub fn attempts(&self, action_id: &str) -> Result<Vec<StoredAttempt>> {
+        self.query("WHERE action_id = ?1 ORDER BY id", params![action_id])
+    }
+
+    /// Returns the attempt that sent the transaction `tx_hash`, if any.
+    pub fn attempt_by_hash(&self, tx_hash: &str) -> Result<Option<StoredAttempt>> {
+        Ok(self
+            .query("WHERE tx_hash = ?1", params![tx_hash])?
+            .into_iter()
+            .next())
+    }
+
+    /// Returns every attempt that acted on `user`'s auctions in `pool`, or on the
+    /// pool's auctions without a user if `user` is `None`, oldest first.
+    pub fn auction_attempts(&self, pool: &str, user: Option<&str>) -> Result<Vec<StoredAttempt>> {
+        match user {
+            Some(user) => self.query(
+                "WHERE pool = ?1 AND user = ?2 ORDER BY id",
+                params![pool, user],
+            ),
+            None => self.query("WHERE pool = ?1 ORDER BY id", params![pool]),
+        }
+    }
+
+    /// Returns the `limit` latest attempts, newest first.
+    pub fn recent_attempts(&self, limit: u32) -> Result<Vec<StoredAttempt>> {
+        self.query("ORDER BY id DESC LIMIT ?1", params![limit])
+    }
+
+    fn query(&self, filter: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<StoredAttempt>> {
+        let db = Connection::open(&self.path)?;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM tx_attempts {}",
+            ATTEMPT_COLUMNS, filter
+        ))?;
+        let rows = stmt.query_map(params, |row| Ok(read_attempt(row)))?;
+        let mut attempts = vec![];
+        for row in rows {
+            attempts.push(row??);
+        }
+        Ok(attempts)
+    }
+}
+
+impl AttemptStore for TxStore {
+    fn record(&self, attempt: &TxAttempt) -> Result<()> {
+        let (pool, user, auction_type) = auction_of(&attempt.action_id);
+        let db = Connection::open(&self.path)?;
+        db.execute(
+            "INSERT INTO tx_attempts (action_id, pool, user, auction_type, attempt, submitted_at, tx_hash, status, ledger, fee_bid, fee_charged, result_xdr, result, events, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                attempt.action_id,
+                pool,
+                user,
+                auction_type,
+                attempt.attempt,
+                attempt.submitted_at as i64,
+                attempt.tx_hash,
+                serde_json::to_value(&attempt.status)?.as_str(),
+                attempt.ledger,
+                attempt.fee_bid,
+                attempt.fee_charged,
+                attempt.result_xdr,
+                attempt.result,
+                serde_json::to_string(&attempt.events)?,
+                attempt.error,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn read_attempt(row: &Row<'_>) -> Result<StoredAttempt> {
+    let status: String = row.get(8)?;
+    let events: String = row.get(14)?;
+    Ok(StoredAttempt {
+        id: row.get(0)?,
+        pool: row.get(2)?,
+        user: row.get(3)?,
+        auction_type: row.get(4)?,
+        attempt: TxAttempt {
+            action_id: row.get(1)?,
+            attempt: row.get(5)?,
+            submitted_at: row.get::<_, i64>(6)? as u64,
+            tx_hash: row.get(7)?,
+            status: serde_json::from_value::<ExecutionStatus>(status.into())?,
+            ledger: row.get(9)?,
+            fee_bid: row.get(10)?,
+            fee_charged: row.get(11)?,
+            result_xdr: row.get(12)?,
+            result: row.get(13)?,
+            events: serde_json::from_str(&events)?,
+            error: row.get(15)?,
+        },
+    })
+}
+
+/// Returns the pool, user, and auction type an action id names, where it names them.
+fn auction_of(action_id: &str) -> (Option<String>, Option<String>, Option<u32>) {
+    let parts: Vec<&str> = action_id.split(':').collect();
+    let part = |index: usize| parts.get(index).map(|part| part.to_string());
+    match parts[0] {
+        "fill" => (part(1), part(2), parts.get(3).and_then(|t| t.parse().ok())),
+        "new_liquidation_auction" => (part(1), part(2), Some(0)),
+        "new_bad_debt_auction" => (part(1), None, Some(1)),
+        _ => (part(1), part(2), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{auction_of, TxStore};
+    use artemis_core::{
+        collectors::execution_result_collector::ExecutionStatus,
+        executors::attempts::{AttemptStore, TxAttempt},
+    };
+
+    #[test]
+    fn test_tx_store() {
+        assert_eq!(
+            auction_of("fill:pool:user:2:100"),
+            (Some("pool".to_string()), Some("user".to_string()), Some(2))
+        );
+        assert_eq!(
+            auction_of("new_bad_debt_auction:pool"),
+            (Some("pool".to_string()), None, Some(1))
+        );
+
+        let dir = std::env::temp_dir().join(format!("tx_store_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = TxStore::new(dir.to_str().unwrap()).unwrap();
+        let unsent = TxAttempt::unsent(
+            "fill:pool:user:0:100",
+            0,
+            1,
+            "simulation failed".to_string(),
+        );
+        let sent = TxAttempt {
+            attempt: 1,
+            tx_hash: Some("hash".to_string()),
+            status: ExecutionStatus::Success,
+            ledger: Some(100),
+            fee_bid: Some(10_300),
+            fee_charged: Some(10_200),
+            result: Some("TxSuccess".to_string()),
+            events: vec!["event".to_string()],
+            error: None,
+            ..unsent.clone()
+        };
+        store.record(&unsent).unwrap();
+        store.record(&sent).unwrap();
+
+        let attempts = store.attempts("fill:pool:user:0:100").unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].attempt, unsent);
+        assert_eq!(attempts[1].attempt, sent);
+        assert_eq!(attempts[1].auction_type, Some(0));
+        assert_eq!(
+            store.attempt_by_hash("hash").unwrap().unwrap().attempt,
+            sent
+        );
+        assert_eq!(
+            store.auction_attempts("pool", Some("user")).unwrap().len(),
+            2
+        );
+        assert_eq!(store.recent_attempts(1).unwrap()[0].attempt, sent);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
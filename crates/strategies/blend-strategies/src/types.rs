@@ -1,18 +1,43 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use artemis_core::{
-    collectors::block_collector::NewBlock, executors::soroban_executor::SubmitStellarTx,
+    collectors::{
+        account_collector::AccountEvent, block_collector::NewBlock,
+        execution_result_collector::ExecutionResult, ledger_entry_collector::LedgerEntryChange,
+        log_collector::EventGap,
+    },
+    delivery::Interest,
+    executors::soroban_executor::SubmitStellarTx,
+    utilities::rpc::RpcClient,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use soroban_fixed_point_math::FixedPoint;
 use stellar_rpc_client::Event as SorobanEvent;
+use stellar_xdr::curr::{Limits, ScSymbol, ScVal, StringM, WriteXdr};
 
 use crate::constants::SCALAR_7;
+use crate::pool_collector::{PoolDiscovered, PoolFilter};
+use crate::price_collector::PriceUpdate;
+use crate::wallet_collector::BalanceUpdate;
 /// Core Event enum for the current strategy.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Event {
     SorobanEvents(Box<SorobanEvent>),
     NewBlock(Box<NewBlock>),
+    ExecutionResult(Box<ExecutionResult>),
+    /// Soroban events may have been missed, so state built from them is stale.
+    EventGap(Box<EventGap>),
+    PriceUpdate(Box<PriceUpdate>),
+    /// A pool was adopted and needs syncing before its events are handled.
+    PoolDiscovered(Box<PoolDiscovered>),
+    LedgerEntryChange(Box<LedgerEntryChange>),
+    BalanceUpdate(Box<BalanceUpdate>),
+    AccountEvent(Box<AccountEvent>),
 }
 
 /// Core Action enum for the current strategy.
@@ -21,10 +46,133 @@ pub enum Action {
     SubmitTx(SubmitStellarTx),
 }
 
+/// The events a strategy wants to receive. Soroban event topics are compared in
+/// their encoded form, so routing an event never decodes its XDR.
+#[derive(Debug, Clone, Default)]
+pub struct EventInterest {
+    new_blocks: bool,
+    execution_results: bool,
+    price_updates: bool,
+    pool_discovery: bool,
+    ledger_entries: bool,
+    balance_updates: bool,
+    account_events: bool,
+    soroban_events: bool,
+    /// Shared with clones, so contracts can be added after the interest is handed
+    /// to the engine.
+    contract_ids: Arc<RwLock<HashSet<String>>>,
+    topics: HashSet<String>,
+}
+
+impl EventInterest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive new block events.
+    pub fn with_new_blocks(mut self) -> Self {
+        self.new_blocks = true;
+        self
+    }
+
+    /// Receive the results of executed actions.
+    pub fn with_execution_results(mut self) -> Self {
+        self.execution_results = true;
+        self
+    }
+
+    /// Receive oracle price changes.
+    pub fn with_price_updates(mut self) -> Self {
+        self.price_updates = true;
+        self
+    }
+
+    /// Receive changes to our token balances.
+    pub fn with_balance_updates(mut self) -> Self {
+        self.balance_updates = true;
+        self
+    }
+
+    /// Receive changes to our classic account.
+    pub fn with_account_events(mut self) -> Self {
+        self.account_events = true;
+        self
+    }
+
+    /// Receive changes to watched ledger entries.
+    pub fn with_ledger_entries(mut self) -> Self {
+        self.ledger_entries = true;
+        self
+    }
+
+    /// Receive discovered pools.
+    pub fn with_pool_discovery(mut self) -> Self {
+        self.pool_discovery = true;
+        self
+    }
+
+    /// Receive soroban events emitted by one of `contract_ids` whose first topic is
+    /// one of the `topics` symbols, and any gap in them. Empty lists match anything.
+    pub fn with_soroban_events(mut self, contract_ids: &[String], topics: &[&str]) -> Self {
+        self.soroban_events = true;
+        self.contract_ids
+            .write()
+            .unwrap()
+            .extend(contract_ids.iter().cloned());
+        self.topics.extend(topics.iter().filter_map(|topic| {
+            ScVal::Symbol(ScSymbol(StringM::from_str(topic).ok()?))
+                .to_xdr_base64(Limits::none())
+                .ok()
+        }));
+        self
+    }
+
+    /// Receives the soroban events of `contract_id` too, in this interest and
+    /// every clone of it. Does nothing if events of every contract match already.
+    pub fn add_contract_id(&self, contract_id: &str) {
+        let mut contract_ids = self.contract_ids.write().unwrap();
+        if !contract_ids.is_empty() {
+            contract_ids.insert(contract_id.to_string());
+        }
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        match event {
+            Event::NewBlock(_) => self.new_blocks,
+            Event::ExecutionResult(_) => self.execution_results,
+            Event::EventGap(_) => self.soroban_events,
+            Event::PriceUpdate(_) => self.price_updates,
+            Event::LedgerEntryChange(_) => self.ledger_entries,
+            Event::BalanceUpdate(_) => self.balance_updates,
+            Event::AccountEvent(_) => self.account_events,
+            Event::PoolDiscovered(_) => self.pool_discovery,
+            Event::SorobanEvents(event) => {
+                let contract_ids = self.contract_ids.read().unwrap();
+                self.soroban_events
+                    && (contract_ids.is_empty() || contract_ids.contains(&event.contract_id))
+                    && (self.topics.is_empty()
+                        || event
+                            .topic
+                            .first()
+                            .is_some_and(|topic| self.topics.contains(topic)))
+            }
+        }
+    }
+}
+
+impl From<EventInterest> for Interest<Event> {
+    fn from(interest: EventInterest) -> Self {
+        Interest::matching(move |event| interest.matches(event))
+    }
+}
+
 /// Configuration for variables we need to pass to the strategy.
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub rpc_url: String,
+    /// RPC endpoints to fail over to when `rpc_url` is unhealthy.
+    #[serde(default)]
+    pub rpc_fallback_urls: Vec<String>,
     pub network_passphrase: String,
     pub db_path: String,
     pub slack_api_url_key: String,
@@ -41,6 +189,26 @@ pub struct Config {
     pub required_profit_pct: i128,
     pub oracle_decimals: u32,
     pub force_fill: bool,
+    /// The pool factory to discover new pools from. Pools are only discovered
+    /// when it is set.
+    #[serde(default)]
+    pub pool_factory: Option<String>,
+    /// Which discovered pools get adopted.
+    #[serde(default)]
+    pub pool_filter: PoolFilter,
+    /// The Horizon server to follow our classic account through. The account
+    /// isn't followed when it is unset.
+    #[serde(default)]
+    pub horizon_url: Option<String>,
+}
+
+impl Config {
+    /// Returns a client for `rpc_url` that fails over to `rpc_fallback_urls`.
+    pub fn rpc_client(&self) -> anyhow::Result<RpcClient> {
+        let mut urls = vec![self.rpc_url.clone()];
+        urls.extend(self.rpc_fallback_urls.iter().cloned());
+        Ok(RpcClient::new(&urls)?.with_health_checks(Duration::from_secs(10)))
+    }
 }
 #[derive(Debug, Clone, Deserialize)]
 pub struct PendingFill {
@@ -169,7 +337,64 @@ impl AuctionData {
 
 #[cfg(test)]
 mod tests {
-    use super::AuctionData;
+    use super::{AuctionData, Event, EventInterest};
+    use crate::pool_collector::{PoolDiscovered, PoolSource};
+    use artemis_core::collectors::{block_collector::NewBlock, log_collector::EventGap};
+    use stellar_rpc_client::Event as SorobanEvent;
+    use stellar_xdr::curr::{Limits, ScSymbol, ScVal, StringM, WriteXdr};
+
+    fn soroban_event(contract_id: &str, name: &str) -> Event {
+        let topic = ScVal::Symbol(ScSymbol(StringM::try_from(name).unwrap()))
+            .to_xdr_base64(Limits::none())
+            .unwrap();
+        Event::SorobanEvents(Box::new(SorobanEvent {
+            event_type: "contract".to_string(),
+            ledger: 1,
+            ledger_closed_at: String::new(),
+            id: String::new(),
+            paging_token: String::new(),
+            contract_id: contract_id.to_string(),
+            topic: vec![topic],
+            value: String::new(),
+        }))
+    }
+
+    #[test]
+    fn test_event_interest() {
+        let interest = EventInterest::new()
+            .with_new_blocks()
+            .with_soroban_events(&["pool".to_string()], &["fill_auction"]);
+        assert!(interest.matches(&Event::NewBlock(Box::new(NewBlock {
+            number: 1,
+            close_time: None,
+            protocol_version: 21,
+        }))));
+        assert!(interest.matches(&soroban_event("pool", "fill_auction")));
+        assert!(!interest.matches(&soroban_event("pool", "supply")));
+        assert!(!interest.matches(&soroban_event("other_pool", "fill_auction")));
+        let gap = EventGap {
+            from_ledger: 1,
+            to_ledger: 2,
+        };
+        assert!(interest.matches(&Event::EventGap(Box::new(gap.clone()))));
+        assert!(!EventInterest::new()
+            .with_new_blocks()
+            .matches(&Event::EventGap(Box::new(gap))));
+
+        // an added pool's events match from then on, in every clone
+        let interest = interest.with_pool_discovery();
+        let routed = interest.clone();
+        assert!(
+            interest.matches(&Event::PoolDiscovered(Box::new(PoolDiscovered {
+                pool: "new_pool".to_string(),
+                source: PoolSource::Factory,
+                ledger: 1,
+            })))
+        );
+        assert!(!routed.matches(&soroban_event("new_pool", "fill_auction")));
+        interest.add_contract_id("new_pool");
+        assert!(routed.matches(&soroban_event("new_pool", "fill_auction")));
+    }
 
     #[test]
     fn test_scale_auction() {
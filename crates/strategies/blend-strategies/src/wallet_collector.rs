@@ -0,0 +1,376 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use anyhow::Result;
+use artemis_core::{
+    collectors::{account_collector::AccountState, ledger_entry_collector::KEY_BATCH_LIMIT},
+    types::{Collector, CollectorStream},
+    utilities::{backoff::Backoff, rpc::RpcClient},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use stellar_xdr::curr::{
+    AccountEntry, AccountEntryExt, AccountEntryExtensionV1Ext, AccountId, AlphaNum12, AlphaNum4,
+    AssetCode12, AssetCode4, LedgerEntryData, LedgerKey, LedgerKeyAccount, LedgerKeyContractData,
+    LedgerKeyTrustLine, Limits, ReadXdr, ScAddress, ScMapEntry, ScSymbol, ScVal, ScVec, StringM,
+    TrustLineAsset, TrustLineEntry, TrustLineEntryExt, TrustLineFlags,
+};
+use tokio::sync::mpsc;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::warn;
+
+use crate::helper::{get_balance, get_token_name};
+
+/// A new balance of one of our tokens, less what we can't spend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceUpdate {
+    pub token: String,
+    pub balance: i128,
+}
+
+/// Where a token balance is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BalanceSource {
+    /// The account entry, for the native asset.
+    Account(LedgerKey),
+    /// The trustline entry, for a stellar asset contract.
+    TrustLine(LedgerKey),
+    /// The `Balance` contract data entry, for a soroban token.
+    ContractData(LedgerKey),
+    /// A simulated `balance` call, for a token whose name couldn't be read.
+    Simulation,
+}
+
+/// A collector that polls an account's token balances and emits a
+/// [BalanceUpdate](BalanceUpdate) whenever one of them changes. Balances are read
+/// from ledger entries in a single batch where possible, so polling doesn't
+/// simulate a call per token. Every balance is emitted on the first poll.
+pub struct WalletCollector {
+    rpc: RpcClient,
+    account: String,
+    tokens: Vec<String>,
+    poll_interval: Duration,
+    /// The most consecutive failed polls retried before the stream fails.
+    max_retries: u32,
+    backoff: Backoff,
+    sources: HashMap<String, BalanceSource>,
+    balances: HashMap<String, i128>,
+}
+
+impl WalletCollector {
+    pub fn new(rpc: RpcClient, account: String, tokens: Vec<String>) -> Self {
+        Self {
+            rpc,
+            account,
+            tokens,
+            poll_interval: Duration::from_secs(5),
+            max_retries: 5,
+            backoff: Backoff::default(),
+            sources: HashMap::new(),
+            balances: HashMap::new(),
+        }
+    }
+
+    /// Sets how often the balances are polled.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Retries up to `max_retries` consecutive failed polls, waiting `backoff`
+    /// between them, before failing the stream.
+    pub fn with_retries(mut self, max_retries: u32, backoff: Backoff) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+}
+
+/// Picks where to read `account`'s balance of `token` from, given the token's name.
+fn balance_source(account: &str, token: &str, name: &str) -> Result<BalanceSource> {
+    if name == "native" {
+        return Ok(BalanceSource::Account(LedgerKey::Account(
+            LedgerKeyAccount {
+                account_id: AccountId::from_str(account)?,
+            },
+        )));
+    }
+    // a stellar asset contract is named after its classic asset, and keeps the
+    // balances of accounts, but not contracts, in trustlines
+    if let (Some((code, issuer)), Ok(account_id)) =
+        (name.split_once(':'), AccountId::from_str(account))
+    {
+        if let Ok(issuer) = AccountId::from_str(issuer) {
+            let asset = match code.len() {
+                1..=4 => {
+                    let mut asset_code = [0; 4];
+                    asset_code[..code.len()].copy_from_slice(code.as_bytes());
+                    TrustLineAsset::CreditAlphanum4(AlphaNum4 {
+                        asset_code: AssetCode4(asset_code),
+                        issuer,
+                    })
+                }
+                _ => {
+                    let mut asset_code = [0; 12];
+                    asset_code[..code.len().min(12)]
+                        .copy_from_slice(&code.as_bytes()[..code.len().min(12)]);
+                    TrustLineAsset::CreditAlphanum12(AlphaNum12 {
+                        asset_code: AssetCode12(asset_code),
+                        issuer,
+                    })
+                }
+            };
+            return Ok(BalanceSource::TrustLine(LedgerKey::Trustline(
+                LedgerKeyTrustLine { account_id, asset },
+            )));
+        }
+    }
+    let key = ScVal::Vec(Some(ScVec::try_from(vec![
+        ScVal::Symbol(ScSymbol(StringM::from_str("Balance")?)),
+        ScVal::Address(ScAddress::from_str(account)?),
+    ])?));
+    Ok(BalanceSource::ContractData(LedgerKey::ContractData(
+        LedgerKeyContractData {
+            contract: ScAddress::from_str(token)?,
+            key,
+            durability: stellar_xdr::curr::ContractDataDurability::Persistent,
+        },
+    )))
+}
+
+/// Reads the native balance an account can spend, like the native stellar asset
+/// contract does, from its account entry.
+fn account_balance(account: &AccountEntry) -> i128 {
+    let (selling, num_sponsoring, num_sponsored) = match &account.ext {
+        AccountEntryExt::V0 => (0, 0, 0),
+        AccountEntryExt::V1(v1) => match &v1.ext {
+            AccountEntryExtensionV1Ext::V0 => (v1.liabilities.selling, 0, 0),
+            AccountEntryExtensionV1Ext::V2(v2) => {
+                (v1.liabilities.selling, v2.num_sponsoring, v2.num_sponsored)
+            }
+        },
+    };
+    AccountState {
+        subentry_count: account.num_sub_entries,
+        num_sponsoring,
+        num_sponsored,
+        balances: [("native".to_string(), account.balance as i128)].into(),
+        selling_liabilities: [("native".to_string(), selling as i128)].into(),
+        ..Default::default()
+    }
+    .spendable_balance()
+}
+
+/// Reads the balance an account can spend from a trustline, like a stellar asset
+/// contract does. Nothing can be spent from a trustline that isn't authorized.
+fn trustline_balance(trustline: &TrustLineEntry) -> i128 {
+    if trustline.flags & TrustLineFlags::AuthorizedFlag as u32 == 0 {
+        return 0;
+    }
+    let selling = match &trustline.ext {
+        TrustLineEntryExt::V0 => 0,
+        TrustLineEntryExt::V1(v1) => v1.liabilities.selling,
+    };
+    (trustline.balance as i128 - selling as i128).max(0)
+}
+
+/// Reads a spendable balance from a ledger entry.
+fn entry_balance(entry: &LedgerEntryData) -> Option<i128> {
+    match entry {
+        LedgerEntryData::Account(account) => Some(account_balance(account)),
+        LedgerEntryData::Trustline(trustline) => Some(trustline_balance(trustline)),
+        LedgerEntryData::ContractData(data) => match &data.val {
+            ScVal::I128(balance) => Some(balance.into()),
+            // the token interface stores the amount alongside its authorization flags
+            ScVal::Map(Some(map)) => {
+                map.iter()
+                    .find_map(|ScMapEntry { key, val }| match (key, val) {
+                        (ScVal::Symbol(key), ScVal::I128(balance))
+                            if key.as_slice() == b"amount" =>
+                        {
+                            Some(balance.into())
+                        }
+                        _ => None,
+                    })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Fetches every balance, reading the ledger entries in batches. A missing entry
+/// is a zero balance.
+async fn fetch_balances(
+    rpc: &RpcClient,
+    account: &str,
+    sources: &HashMap<String, BalanceSource>,
+) -> Result<HashMap<String, i128>> {
+    let mut balances = HashMap::new();
+    let mut keys = HashMap::new();
+    for (token, source) in sources {
+        match source {
+            BalanceSource::Account(key)
+            | BalanceSource::TrustLine(key)
+            | BalanceSource::ContractData(key) => {
+                keys.insert(key.clone(), token.clone());
+                balances.insert(token.clone(), 0);
+            }
+            BalanceSource::Simulation => {
+                let balance = get_balance(rpc, account.to_string(), token.clone()).await?;
+                balances.insert(token.clone(), balance);
+            }
+        }
+    }
+    let batch_keys: Vec<LedgerKey> = keys.keys().cloned().collect();
+    for batch in batch_keys.chunks(KEY_BATCH_LIMIT) {
+        let res = rpc.get_ledger_entries(batch).await?;
+        for entry in res.entries.unwrap_or_default() {
+            let key = LedgerKey::from_xdr_base64(&entry.key, Limits::none())?;
+            let value = LedgerEntryData::from_xdr_base64(&entry.xdr, Limits::none())?;
+            let Some(token) = keys.get(&key) else {
+                continue;
+            };
+            match entry_balance(&value) {
+                Some(balance) => {
+                    balances.insert(token.clone(), balance);
+                }
+                None => warn!("unable to read balance of token {}", token),
+            }
+        }
+    }
+    Ok(balances)
+}
+
+#[async_trait]
+impl Collector<BalanceUpdate> for WalletCollector {
+    async fn get_event_stream<'a>(&'a mut self) -> Result<CollectorStream<'a, BalanceUpdate>> {
+        for token in self.tokens.iter() {
+            if self.sources.contains_key(token) {
+                continue;
+            }
+            let source = match get_token_name(&self.rpc, token).await {
+                Ok(name) => balance_source(&self.account, token, &name)?,
+                Err(e) => {
+                    warn!(
+                        "unable to read name of token {}, simulating its balance: {}",
+                        token, e
+                    );
+                    BalanceSource::Simulation
+                }
+            };
+            self.sources.insert(token.clone(), source);
+        }
+        let (sender, receiver) = mpsc::channel(512);
+        let rpc = self.rpc.clone();
+        let account = self.account.clone();
+        let sources = self.sources.clone();
+        let mut balances = self.balances.clone();
+        let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let mut poll = interval(self.poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        tokio::spawn(async move {
+            let mut attempt = 0;
+            while !sender.is_closed() {
+                poll.tick().await;
+                let latest = match fetch_balances(&rpc, &account, &sources).await {
+                    Ok(latest) => {
+                        attempt = 0;
+                        latest
+                    }
+                    Err(e) if attempt < max_retries => {
+                        let delay = backoff.delay(attempt);
+                        warn!("error fetching balances: {}, retrying in {:?}", e, delay);
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e)).await;
+                        return;
+                    }
+                };
+                for (token, balance) in latest {
+                    if balances.get(&token) == Some(&balance) {
+                        continue;
+                    }
+                    balances.insert(token.clone(), balance);
+                    if sender
+                        .send(Ok(BalanceUpdate { token, balance }))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        });
+        // remember the balances so a restarted stream only emits changes
+        let balances = &mut self.balances;
+        let stream = ReceiverStream::new(receiver).map(move |update: Result<BalanceUpdate>| {
+            if let Ok(update) = &update {
+                balances.insert(update.token.clone(), update.balance);
+            }
+            update
+        });
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{balance_source, trustline_balance, BalanceSource};
+    use std::str::FromStr;
+    use stellar_xdr::curr::{
+        AccountId, AlphaNum4, AssetCode4, LedgerKey, Liabilities, TrustLineAsset, TrustLineEntry,
+        TrustLineEntryExt, TrustLineEntryV1, TrustLineEntryV1Ext, TrustLineFlags,
+    };
+
+    #[test]
+    fn test_balance_source() {
+        let account = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+        let token = "CAQCFVLOBK5GIULPNZRGATJJMIZL5BSP7X5YJVMGCPTUEPFM4AVSRCJU";
+        assert!(matches!(
+            balance_source(account, token, "native").unwrap(),
+            BalanceSource::Account(LedgerKey::Account(_))
+        ));
+        let usdc = format!("USDC:{}", account);
+        assert!(matches!(
+            balance_source(account, token, &usdc).unwrap(),
+            BalanceSource::TrustLine(LedgerKey::Trustline(_))
+        ));
+        assert!(matches!(
+            balance_source(account, token, "Comet LP").unwrap(),
+            BalanceSource::ContractData(LedgerKey::ContractData(_))
+        ));
+    }
+
+    #[test]
+    fn test_trustline_balance() {
+        let account_id =
+            AccountId::from_str("GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF")
+                .unwrap();
+        let mut trustline = TrustLineEntry {
+            account_id: account_id.clone(),
+            asset: TrustLineAsset::CreditAlphanum4(AlphaNum4 {
+                asset_code: AssetCode4(*b"USDC"),
+                issuer: account_id,
+            }),
+            balance: 100_0000000,
+            limit: i64::MAX,
+            flags: TrustLineFlags::AuthorizedFlag as u32,
+            ext: TrustLineEntryExt::V1(TrustLineEntryV1 {
+                liabilities: Liabilities {
+                    buying: 0,
+                    selling: 30_0000000,
+                },
+                ext: TrustLineEntryV1Ext::V0,
+            }),
+        };
+        // offers selling part of the balance hold it back
+        assert_eq!(trustline_balance(&trustline), 70_0000000);
+        trustline.flags = TrustLineFlags::AuthorizedToMaintainLiabilitiesFlag as u32;
+        assert_eq!(trustline_balance(&trustline), 0);
+    }
+}